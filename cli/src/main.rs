@@ -0,0 +1,512 @@
+mod output;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::read_keypair_file;
+use solana_sdk::signer::Signer;
+use structopt::StructOpt;
+
+use flash_loan_sdk::builder::FlashLoanBuilder;
+use flash_loan_sdk::cluster::Cluster;
+use flash_loan_sdk::error::FlashSdkError;
+use flash_loan_sdk::instruction::FlashLoanInstruction;
+use flash_loan_sdk::sender::{SenderConfig, TransactionSender};
+use flash_loan_sdk::transaction::ComputeBudget;
+use flash_loan_sdk::types::Reserve;
+use flash_loan_sdk::{
+    available_liquidity, flash_loan_fee, get_reserve_with_program_id, get_reserves,
+};
+
+use crate::output::Output;
+
+fn main() {
+    let opts = Opts::from_args();
+    match run(&opts) {
+        Ok(value) => println!("{}", opts.output.render(&value)),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(opts: &Opts) -> Result<Value, String> {
+    let program_id = opts.program_id.unwrap_or_else(|| opts.url.program_id());
+    let rpc_client =
+        RpcClient::new_with_commitment(opts.url.url().to_string(), CommitmentConfig::confirmed());
+
+    match &opts.command {
+        Command::Reserve(ReserveCommand::Show { reserve }) => {
+            let reserve_pubkey = resolve_reserve(&opts.url, reserve)?;
+            let reserve = get_reserve(&rpc_client, &program_id, &reserve_pubkey)?;
+            Ok(reserve_details(&reserve_pubkey, &reserve))
+        }
+        Command::Reserves(ReservesCommand::List { mint }) => {
+            let reserves =
+                get_reserves(&program_id, mint.as_ref(), &rpc_client).map_err(sdk_error)?;
+            Ok(Value::Array(
+                reserves
+                    .iter()
+                    .map(|(pubkey, reserve)| {
+                        json!({
+                            "reserve": pubkey.to_string(),
+                            "mint": reserve.liquidity.mint_pubkey.to_string(),
+                            "available_liquidity": liquidity_amount(reserve, available_liquidity(reserve)),
+                            "flash_loan_fee_percent": fee_percent(reserve),
+                        })
+                    })
+                    .collect(),
+            ))
+        }
+        Command::Fee(FeeCommand::Quote {
+            reserve,
+            amount,
+            ui,
+        }) => {
+            let reserve_pubkey = resolve_reserve(&opts.url, reserve)?;
+            let reserve = get_reserve(&rpc_client, &program_id, &reserve_pubkey)?;
+            let amount = base_amount(&reserve, amount, *ui)?;
+            let fee = flash_loan_fee(&reserve, amount).map_err(sdk_error)?;
+            let repay_amount = amount
+                .checked_add(fee)
+                .ok_or_else(|| "Repay amount overflows".to_string())?;
+            Ok(json!({
+                "reserve": reserve_pubkey.to_string(),
+                "amount": amount,
+                "ui_amount": liquidity_amount(&reserve, amount),
+                "fee": fee,
+                "ui_fee": liquidity_amount(&reserve, fee),
+                "repay_amount": repay_amount,
+            }))
+        }
+        Command::Borrow(borrow) => borrow.run(&rpc_client, &opts.url, &program_id),
+        Command::DecodeIx { data } => decode_ix(data),
+    }
+}
+
+/// Decodes base64 instruction `data`
+fn decode_ix(data: &str) -> Result<Value, String> {
+    let data = base64::decode(data).map_err(|err| err.to_string())?;
+    let instruction = FlashLoanInstruction::unpack(&data).map_err(|err| err.to_string())?;
+    Ok(match instruction {
+        FlashLoanInstruction::FlashLoan {
+            amount,
+            receive_flash_loan_instruction_tag,
+        } => json!({
+            "instruction": "FlashLoan",
+            "amount": amount,
+            "receive_flash_loan_instruction_tag": receive_flash_loan_instruction_tag,
+        }),
+        FlashLoanInstruction::FlashBorrow { amount } => json!({
+            "instruction": "FlashBorrow",
+            "amount": amount,
+        }),
+        FlashLoanInstruction::FlashRepay { amount } => json!({
+            "instruction": "FlashRepay",
+            "amount": amount,
+        }),
+    })
+}
+
+/// Reserve pubkey or a symbol of a known reserve of the cluster, e.g. SOL
+fn resolve_reserve(cluster: &Cluster, reserve: &str) -> Result<Pubkey, String> {
+    Pubkey::from_str(reserve)
+        .ok()
+        .or_else(|| cluster.reserve(reserve))
+        .ok_or_else(|| format!("Unknown reserve {}", reserve))
+}
+
+fn get_reserve(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    reserve_pubkey: &Pubkey,
+) -> Result<Reserve, String> {
+    get_reserve_with_program_id(program_id, reserve_pubkey, rpc_client).map_err(sdk_error)
+}
+
+fn reserve_details(reserve_pubkey: &Pubkey, reserve: &Reserve) -> Value {
+    json!({
+        "reserve": reserve_pubkey.to_string(),
+        "version": reserve.version,
+        "last_update_slot": reserve.last_update,
+        "lending_market": reserve.lending_market.to_string(),
+        "mint": reserve.liquidity.mint_pubkey.to_string(),
+        "mint_decimals": reserve.liquidity.mint_decimals,
+        "supply": reserve.liquidity.supply_pubkey.to_string(),
+        "available_liquidity": liquidity_amount(reserve, reserve.liquidity.available_amount),
+        "lp_token_mint": reserve.lp_tokens_info.mint_pubkey.to_string(),
+        "lp_token_supply": reserve.lp_tokens_info.mint_total_supply,
+        "flash_loan_fee_percent": fee_percent(reserve),
+        "texture_fee_percent": reserve.config.fees.texture_fee_percentage,
+        "deposit_limit": reserve
+            .config
+            .deposit_limit_ui_amount(reserve.liquidity.decimals()),
+        "fee_receiver": reserve.config.fee_receiver.to_string(),
+    })
+}
+
+fn liquidity_amount(reserve: &Reserve, amount: u64) -> String {
+    reserve.liquidity.amount_to_ui_amount(amount)
+}
+
+/// Parses `amount` given in base units, or in UI units of the liquidity mint when `ui` is set
+fn base_amount(reserve: &Reserve, amount: &str, ui: bool) -> Result<u64, String> {
+    if ui {
+        reserve
+            .liquidity
+            .ui_amount_to_amount(amount)
+            .map_err(sdk_error)
+    } else {
+        amount
+            .parse()
+            .map_err(|_| format!("Invalid amount {}, expected base units", amount))
+    }
+}
+
+fn fee_percent(reserve: &Reserve) -> String {
+    reserve.config.fees.flash_loan_fee_percent()
+}
+
+fn sdk_error(err: FlashSdkError) -> String {
+    err.to_string()
+}
+
+#[derive(StructOpt)]
+/// Command line tool for the Flash Loan program by Texture
+#[structopt(name = "flash-loan", rename_all = "kebab-case")]
+pub struct Opts {
+    /// Solana cluster to work with: mainnet, devnet, localnet or RPC endpoint URL
+    #[structopt(long, short, default_value = "devnet", global = true)]
+    pub url: Cluster,
+
+    /// Flash Loan program id. Defaults to the cluster's program id.
+    #[structopt(long, global = true)]
+    pub program_id: Option<Pubkey>,
+
+    /// Output format: json or table
+    #[structopt(long, short, default_value = "table", global = true)]
+    pub output: Output,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Command {
+    /// Single reserve
+    Reserve(ReserveCommand),
+    /// All reserves of the program
+    Reserves(ReservesCommand),
+    /// Flash loan fees
+    Fee(FeeCommand),
+    /// Flash borrow and repay in one transaction
+    Borrow(Borrow),
+    /// Decode base64 Flash Loan instruction data
+    DecodeIx { data: String },
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum ReserveCommand {
+    /// Show a decoded reserve
+    Show {
+        /// Reserve pubkey or symbol of a known reserve, e.g. SOL
+        reserve: String,
+    },
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum ReservesCommand {
+    /// List reserves
+    List {
+        /// Only reserves of this liquidity mint
+        #[structopt(long)]
+        mint: Option<Pubkey>,
+    },
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum FeeCommand {
+    /// Flash loan fee of borrowing `amount`
+    Quote {
+        /// Reserve pubkey or symbol of a known reserve, e.g. SOL
+        reserve: String,
+        /// Amount in base units (e.g. lamports), or in tokens (e.g. SOL) with --ui
+        amount: String,
+        /// Amount is in tokens of the liquidity mint, e.g. 1.5
+        #[structopt(long)]
+        ui: bool,
+    },
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Borrow {
+    /// Reserve pubkey or symbol of a known reserve
+    #[structopt(long, short, default_value = "SOL")]
+    pub reserve: String,
+
+    /// Amount to borrow in base units (e.g. lamports), or in tokens (e.g. SOL) with --ui
+    #[structopt(long)]
+    pub amount: String,
+
+    /// Amount is in tokens of the liquidity mint, e.g. 1.5
+    #[structopt(long)]
+    pub ui: bool,
+
+    /// Keypair to use for signing instructions (e.g. authorise transfers from wallet) and pay fees.
+    /// Defaults to ~/.config/solana/id.json.
+    #[structopt(long, short)]
+    pub authority: Option<PathBuf>,
+
+    /// User's token account of the reserve liquidity mint. When omitted, authority's associated
+    /// token account is used and created if missing.
+    #[structopt(long, short)]
+    pub wallet: Option<Pubkey>,
+
+    /// Compute unit limit of the transaction. Sized from a simulation when omitted.
+    #[structopt(long)]
+    pub compute_unit_limit: Option<u32>,
+
+    /// Priority fee in micro-lamports per compute unit.
+    #[structopt(long)]
+    pub compute_unit_price: Option<u64>,
+}
+
+impl Borrow {
+    /// Given authority keypair path or the Solana CLI default one
+    fn authority_path(&self) -> Result<PathBuf, String> {
+        if let Some(path) = &self.authority {
+            return Ok(path.clone());
+        }
+        let mut path = dirs_next::home_dir()
+            .ok_or("Home directory is unknown, pass the keypair with --authority")?;
+        path.extend(&[".config", "solana", "id.json"]);
+        Ok(path)
+    }
+
+    fn run(
+        &self,
+        rpc_client: &RpcClient,
+        cluster: &Cluster,
+        program_id: &Pubkey,
+    ) -> Result<Value, String> {
+        let reserve_pubkey = resolve_reserve(cluster, &self.reserve)?;
+        let reserve = get_reserve(rpc_client, program_id, &reserve_pubkey)?;
+        let amount = base_amount(&reserve, &self.amount, self.ui)?;
+        let fee = flash_loan_fee(&reserve, amount).map_err(sdk_error)?;
+        let authority = read_keypair_file(self.authority_path()?).map_err(|err| err.to_string())?;
+
+        let builder = FlashLoanBuilder::new(
+            *program_id,
+            reserve_pubkey,
+            &reserve,
+            amount,
+            authority.pubkey(),
+        );
+        let builder = match self.wallet {
+            Some(wallet) => builder.liquidity_account(wallet),
+            None => builder.associated_token_account(),
+        };
+        let flash_loan = builder.build_with_fetcher(rpc_client).map_err(sdk_error)?;
+
+        let compute_budget = ComputeBudget {
+            unit_limit: self.compute_unit_limit,
+            unit_price: self.compute_unit_price,
+        };
+        let compute_budget = match compute_budget.unit_limit {
+            Some(_) => compute_budget,
+            None => compute_budget
+                .unit_limit_from_rpc(
+                    rpc_client,
+                    &authority.pubkey(),
+                    &flash_loan.clone().with_instructions(vec![]),
+                    &[],
+                    10,
+                )
+                .map_err(sdk_error)?,
+        };
+
+        let report = TransactionSender::new(rpc_client, *program_id, SenderConfig::default())
+            .send(
+                &authority.pubkey(),
+                &compute_budget.with_instructions(&flash_loan.with_instructions(vec![])),
+                &[&authority],
+            )
+            .map_err(sdk_error)?;
+        if let Some(error) = report.error {
+            return Err(match report.flash_error {
+                Some(flash_error) => format!("{}: {}", error, flash_error),
+                None => error.to_string(),
+            });
+        }
+        let signature = report.signature;
+
+        Ok(json!({
+            "signature": signature.to_string(),
+            "reserve": reserve_pubkey.to_string(),
+            "amount": amount,
+            "fee": fee,
+            "compute_unit_limit": compute_budget.unit_limit,
+            "compute_unit_price": compute_budget.unit_price,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use flash_loan_sdk::testing::ReserveBuilder;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Opts {
+        Opts::from_iter_safe(std::iter::once("flash-loan").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn parse_options() {
+        let opts = parse(&["reserve", "show", "SOL"]);
+        assert_eq!(opts.url, Cluster::Devnet);
+        assert_eq!(opts.program_id, None);
+        assert_eq!(opts.output, Output::Table);
+        assert!(matches!(
+            opts.command,
+            Command::Reserve(ReserveCommand::Show { ref reserve }) if reserve == "SOL"
+        ));
+
+        // Global options are accepted after the subcommand
+        let program_id = Pubkey::new_unique();
+        let opts = parse(&[
+            "fee",
+            "quote",
+            "SOL",
+            "1.5",
+            "--ui",
+            "--url",
+            "mainnet",
+            "--program-id",
+            &program_id.to_string(),
+            "--output",
+            "json",
+        ]);
+        assert_eq!(opts.url, Cluster::Mainnet);
+        assert_eq!(opts.program_id, Some(program_id));
+        assert_eq!(opts.output, Output::Json);
+        assert!(matches!(
+            opts.command,
+            Command::Fee(FeeCommand::Quote { ref amount, ui: true, .. }) if amount == "1.5"
+        ));
+
+        match parse(&["borrow", "--amount", "1000", "-a", "id.json"]).command {
+            Command::Borrow(borrow) => {
+                assert_eq!(borrow.reserve, "SOL");
+                assert_eq!(borrow.amount, "1000");
+                assert!(!borrow.ui);
+                assert_eq!(borrow.authority_path(), Ok(PathBuf::from("id.json")));
+                assert_eq!(borrow.wallet, None);
+            }
+            _ => panic!("expected borrow"),
+        }
+        // The default keypair is looked up only when borrowing
+        match parse(&["borrow", "--amount", "1000"]).command {
+            Command::Borrow(borrow) => {
+                assert_eq!(borrow.authority, None);
+                if let Some(home) = dirs_next::home_dir() {
+                    assert_eq!(
+                        borrow.authority_path(),
+                        Ok(home.join(".config/solana/id.json"))
+                    );
+                }
+            }
+            _ => panic!("expected borrow"),
+        }
+
+        assert!(Opts::from_iter_safe(&["flash-loan", "borrow"]).is_err());
+        assert!(
+            Opts::from_iter_safe(&["flash-loan", "reserve", "show", "SOL", "-o", "yaml"]).is_err()
+        );
+        // A misspelt cluster is not taken for a URL
+        assert!(
+            Opts::from_iter_safe(&["flash-loan", "reserve", "show", "SOL", "-u", "devnte"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_amounts() {
+        let reserve = ReserveBuilder::new().decimals(6).build();
+        assert_eq!(base_amount(&reserve, "1500000", false), Ok(1_500_000));
+        assert_eq!(base_amount(&reserve, "1.5", true), Ok(1_500_000));
+        assert!(base_amount(&reserve, "1.5", false).is_err());
+        assert_eq!(
+            base_amount(&reserve, "0.0000001", true),
+            Err("InvalidAmount".to_string())
+        );
+    }
+
+    #[test]
+    fn decode_instruction_json() {
+        assert_eq!(
+            decode_ix("BwDKmjsAAAAA"),
+            Ok(json!({"instruction": "FlashBorrow", "amount": 1_000_000_000}))
+        );
+        let flash_loan = FlashLoanInstruction::FlashLoan {
+            amount: 42,
+            receive_flash_loan_instruction_tag: 3,
+        };
+        assert_eq!(
+            decode_ix(&base64::encode(flash_loan.pack())),
+            Ok(json!({
+                "instruction": "FlashLoan",
+                "amount": 42,
+                "receive_flash_loan_instruction_tag": 3,
+            }))
+        );
+        assert!(decode_ix("not base64!").is_err());
+        assert!(decode_ix(&base64::encode([9])).is_err());
+    }
+
+    #[test]
+    fn reserve_details_json() {
+        let reserve_pubkey = Pubkey::new_unique();
+        let builder = ReserveBuilder::new()
+            .decimals(6)
+            .available_liquidity(2_500_000)
+            .last_update(100);
+        let reserve = builder.build();
+
+        assert_eq!(
+            reserve_details(&reserve_pubkey, &reserve),
+            json!({
+                "reserve": reserve_pubkey.to_string(),
+                "version": reserve.version,
+                "last_update_slot": 100,
+                "lending_market": reserve.lending_market.to_string(),
+                "mint": reserve.liquidity.mint_pubkey.to_string(),
+                "mint_decimals": 6,
+                "supply": reserve.liquidity.supply_pubkey.to_string(),
+                "available_liquidity": "2.5",
+                "lp_token_mint": reserve.lp_tokens_info.mint_pubkey.to_string(),
+                "lp_token_supply": 2_500_000,
+                "flash_loan_fee_percent": "0.3",
+                "texture_fee_percent": 20,
+                "deposit_limit": "unlimited",
+                "fee_receiver": reserve.config.fee_receiver.to_string(),
+            })
+        );
+
+        let reserve = builder.deposit_limit(10_000_000).build();
+        assert_eq!(
+            reserve_details(&reserve_pubkey, &reserve)["deposit_limit"],
+            "10"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Command output as JSON or a table
+
+use std::str::FromStr;
+
+use serde_json::Value;
+
+/// Output format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    Json,
+    Table,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Output::Json),
+            "table" => Ok(Output::Table),
+            _ => Err(format!("Unknown output format {}, use json or table", s)),
+        }
+    }
+}
+
+impl Output {
+    /// Renders `value`. A table shows an object as key/value rows and an array of objects as
+    /// one row per object with a column per key of the first object.
+    pub fn render(&self, value: &Value) -> String {
+        match self {
+            Output::Json => serde_json::to_string_pretty(value).expect("Serializing output"),
+            Output::Table => match value {
+                Value::Object(object) => table(
+                    None,
+                    object
+                        .iter()
+                        .map(|(key, value)| vec![key.clone(), cell(value)])
+                        .collect(),
+                ),
+                Value::Array(rows) => {
+                    let header = match rows.first() {
+                        Some(Value::Object(object)) => object.keys().cloned().collect(),
+                        _ => vec![],
+                    };
+                    let rows = rows
+                        .iter()
+                        .map(|row| match row {
+                            Value::Object(object) => header
+                                .iter()
+                                .map(|key| object.get(key).map(cell).unwrap_or_default())
+                                .collect(),
+                            value => vec![cell(value)],
+                        })
+                        .collect();
+                    table(Some(header), rows)
+                }
+                value => cell(value),
+            },
+        }
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn table(header: Option<Vec<String>>, rows: Vec<Vec<String>>) -> String {
+    let all_rows = header.iter().chain(rows.iter()).collect::<Vec<_>>();
+    let columns = all_rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let widths = (0..columns)
+        .map(|column| {
+            all_rows
+                .iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let line = |row: &Vec<String>| {
+        row.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![];
+    if let Some(header) = &header {
+        lines.push(line(header));
+        lines.push(
+            widths
+                .iter()
+                .map(|width| "-".repeat(*width))
+                .collect::<Vec<_>>()
+                .join("  "),
+        );
+    }
+    lines.extend(rows.iter().map(line));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn render_table() {
+        assert_eq!(
+            Output::Table.render(&json!({"mint": "So11", "decimals": 9})),
+            "mint      So11\ndecimals  9"
+        );
+        assert_eq!(
+            Output::Table.render(&json!([
+                {"reserve": "A", "fee": "0.3"},
+                {"reserve": "BBB", "fee": "1"},
+            ])),
+            "reserve  fee\n-------  ---\nA        0.3\nBBB      1"
+        );
+    }
+
+    #[test]
+    fn render_json() {
+        assert_eq!(
+            Output::Json.render(&json!({"amount": 1})),
+            "{\n  \"amount\": 1\n}"
+        );
+        assert!("yaml".parse::<Output>().is_err());
+    }
+}
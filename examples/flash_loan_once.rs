@@ -1,42 +1,55 @@
 use std::path::PathBuf;
 
 use derive_more::FromStr;
-use num_traits::pow;
-use solana_client::client_error::ClientError;
 use solana_client::rpc_client::RpcClient;
-use solana_program::instruction::Instruction;
 use solana_program::pubkey::Pubkey;
 use solana_sdk::commitment_config::CommitmentConfig;
-use solana_sdk::signature::{read_keypair_file, Signature};
+use solana_sdk::signature::read_keypair_file;
 use solana_sdk::signer::Signer;
-use solana_sdk::signers::Signers;
-use solana_sdk::transaction::Transaction;
 use structopt::StructOpt;
 
+use flash_loan_sdk::builder::FlashLoanBuilder;
+use flash_loan_sdk::cluster::Cluster;
 use flash_loan_sdk::instruction::{flash_borrow, flash_repay};
-use flash_loan_sdk::{available_liquidity, flash_loan_fee, get_reserve, FLASH_LOAN_ID};
+use flash_loan_sdk::sender::{SenderConfig, TransactionSender};
+use flash_loan_sdk::transaction::ComputeBudget;
+use flash_loan_sdk::{available_liquidity, flash_loan_fee, get_reserve_with_program_id};
 
 fn main() {
     let opt = Opts::from_args();
+    let program_id = opt.program_id.unwrap_or_else(|| opt.url.program_id());
+    let reserve_pubkey = opt
+        .reserve
+        .or_else(|| opt.url.reserve("SOL"))
+        .expect("No known wrapped SOL reserve on this cluster, specify --reserve");
 
     println!("=====================Setup=====================");
-    println!("Solana cluster       : {}", opt.url);
-    println!("Flash loan program id: {}", opt.program_id);
-    println!("Flash loan reserve   : {}", opt.reserve);
+    println!("Solana cluster       : {}", opt.url.url());
+    println!("Flash loan program id: {}", program_id);
+    println!("Flash loan reserve   : {}", reserve_pubkey);
     println!("===============================================");
 
-    let rpc_client = RpcClient::new_with_commitment(opt.url.clone(), CommitmentConfig::confirmed());
+    let rpc_client =
+        RpcClient::new_with_commitment(opt.url.url().to_string(), CommitmentConfig::confirmed());
 
     // From Solana RPC rate limit perspective it is more efficient to load Reserve once from the chain and then
     // use it in subsequent calls.
-    let reserve = get_reserve(&opt.reserve, &rpc_client).expect("Getting reserve");
+    let reserve = get_reserve_with_program_id(&program_id, &reserve_pubkey, &rpc_client)
+        .expect("Getting reserve");
 
     // All token amounts in this SDK are in lamports (or equivalent fractional token units).
     // To calculate fractional units from human readable amount do this...
-    let amount_to_borrow = pow(10, reserve.liquidity.mint_decimals as usize) * 10; // Gives 10 SOL amount expressed as lamports
+    let amount_to_borrow = reserve
+        .liquidity
+        .ui_amount_to_amount("10")
+        .expect("Converting UI amount"); // Gives 10 SOL amount expressed as lamports
 
     let available_liquidity = available_liquidity(&reserve);
-    println!("Available liquidity: {} lamports", available_liquidity);
+    println!(
+        "Available liquidity: {} lamports ({} tokens)",
+        available_liquidity,
+        reserve.liquidity.amount_to_ui_amount(available_liquidity)
+    );
 
     // We need to return amount_to_borrow + fee at the end of the flash loan transaction. So its worth
     // to make sure that we have enough money to pay fees. Get the fees amount via flash_loan_fee() call.
@@ -46,92 +59,121 @@ fn main() {
         amount_to_borrow, fee
     );
 
-    // Construct FlashBorrow instruction. Here we specify amount_to_borrow without fees.
-    let flash_borrow_ix = flash_borrow(
-        opt.program_id,
-        amount_to_borrow,
-        reserve.liquidity.supply_pubkey,
-        opt.wallet,
-        opt.reserve,
-        reserve.lending_market,
-    );
-
     let authority_kp = read_keypair_file(opt.authority.0).expect("Reading authority key pair file");
 
-    // Construct FlashRepay instruction. Again we specify amount_to_borrow without fees.
-    // But when contract will be executing this IX it will transfer amount_to_borrow + fee from user's wallet!
-    let flash_repay_ix = flash_repay(
-        opt.program_id,
-        amount_to_borrow,
-        opt.wallet,
-        reserve.liquidity.supply_pubkey,
-        reserve.config.fee_receiver,
-        opt.reserve,
-        reserve.lending_market,
-        authority_kp.pubkey(),
-    );
-
-    // Put FlashBorrow first and FlashRepay thereafter. This is simplified example. In real world
-    // applications there will be other instructions in between (e.g. swaps on DEXes).
-    // Those instructions will be able to use borrowed tokens in their logic.
-    sign_and_send_transaction(
-        &authority_kp.pubkey(),
-        &[
-            flash_borrow_ix,
-            /* IXes which use borrowed amount go here*/ flash_repay_ix,
-        ],
-        &[&authority_kp],
-        &rpc_client,
-    )
-    .expect("Sending TX");
+    let ixs = match opt.wallet {
+        Some(wallet) => {
+            // Construct FlashBorrow instruction. Here we specify amount_to_borrow without fees.
+            let flash_borrow_ix = flash_borrow(
+                program_id,
+                amount_to_borrow,
+                reserve.liquidity.supply_pubkey,
+                wallet,
+                reserve_pubkey,
+                reserve.lending_market,
+            );
+
+            // Construct FlashRepay instruction. Again we specify amount_to_borrow without fees.
+            // But when contract will be executing this IX it will transfer amount_to_borrow + fee from user's wallet!
+            let flash_repay_ix = flash_repay(
+                program_id,
+                amount_to_borrow,
+                wallet,
+                reserve.liquidity.supply_pubkey,
+                reserve.config.fee_receiver,
+                reserve_pubkey,
+                reserve.lending_market,
+                authority_kp.pubkey(),
+            );
+
+            // Put FlashBorrow first and FlashRepay thereafter. This is simplified example. In real world
+            // applications there will be other instructions in between (e.g. swaps on DEXes).
+            // Those instructions will be able to use borrowed tokens in their logic.
+            vec![
+                flash_borrow_ix,
+                /* IXes which use borrowed amount go here*/ flash_repay_ix,
+            ]
+        }
+        None => {
+            // Without a wallet FlashLoanBuilder uses authority's wrapped SOL account and funds it with the fee.
+            // If the account does not exist yet, it is created and closed after FlashRepay to get lamports back.
+            FlashLoanBuilder::new(
+                program_id,
+                reserve_pubkey,
+                &reserve,
+                amount_to_borrow,
+                authority_kp.pubkey(),
+            )
+            .build_with_fetcher(&rpc_client)
+            .expect("Building flash loan instructions")
+            .with_instructions(vec![/* IXes which use borrowed amount go here*/])
+        }
+    };
+
+    // Compute budget instructions go in front of the transaction. Under contention a priority fee
+    // helps to get the transaction included. When no limit is given it is sized from a simulation.
+    let compute_budget = ComputeBudget {
+        unit_limit: opt.compute_unit_limit,
+        unit_price: opt.compute_unit_price,
+    };
+    let compute_budget = match compute_budget.unit_limit {
+        Some(_) => compute_budget,
+        None => compute_budget
+            .unit_limit_from_rpc(&rpc_client, &authority_kp.pubkey(), &ixs, &[], 10)
+            .expect("Simulating transaction"),
+    };
+    let ixs = compute_budget.with_instructions(&ixs);
+
+    // The sender resends with a new blockhash when the transaction does not land in time and
+    // decodes the Flash Loan program error when it fails.
+    let report = TransactionSender::new(&rpc_client, program_id, SenderConfig::default())
+        .send(&authority_kp.pubkey(), &ixs, &[&authority_kp])
+        .expect("Sending TX");
+    println!("Signature: {}", report.signature);
+    if let Some(error) = report.error {
+        panic!(
+            "Flash loan failed: {} (Flash Loan program error: {:?})",
+            error, report.flash_error
+        );
+    }
 
     println!("Successfully flash borrowed!");
 }
 
-fn sign_and_send_transaction(
-    payer: &Pubkey,
-    ixs: impl AsRef<[Instruction]>,
-    signers: &impl Signers,
-    rpc_client: &RpcClient,
-) -> Result<Signature, ClientError> {
-    let mut tx = Transaction::new_with_payer(ixs.as_ref(), Some(payer));
-    let blockhash = rpc_client.get_latest_blockhash()?;
-
-    tx.sign(signers, blockhash);
-
-    println!("Sending transaction {:?}", tx);
-
-    let signature = rpc_client.send_and_confirm_transaction_with_spinner(&tx)?;
-
-    println!("Signature: {}", signature);
-    Ok(signature)
-}
-
 #[derive(StructOpt)]
 /// This is Flash Loan SDK example. By default it connects to Solana Devnet claster with
 /// preconfigured Flash Loan program and Reserves. Namely this program works with wrapped
 /// SOL reserve.
 #[structopt(rename_all = "kebab-case")]
 pub struct Opts {
-    /// Flash Loan program id. Defaults to Devnet address.
-    #[structopt(long, default_value = FLASH_LOAN_ID)]
-    pub program_id: Pubkey,
+    /// Flash Loan program id. Defaults to the cluster's program id.
+    #[structopt(long)]
+    pub program_id: Option<Pubkey>,
 
     /// Keypair to use for signing instructions (e.g. authorise transfers from wallet) and pay fees.
     #[structopt(long, short, default_value)]
     pub authority: KeypairPath,
 
-    /// User's SPL Token wallet of native mint (aka wrapped SOL)
+    /// User's SPL Token wallet of native mint (aka wrapped SOL). When omitted, authority's wrapped SOL
+    /// associated token account is created and closed within the transaction.
     #[structopt(long, short)]
-    pub wallet: Pubkey,
+    pub wallet: Option<Pubkey>,
 
-    /// Solana RPC endpoint to work with
-    #[structopt(long, short, default_value = "https://api.devnet.solana.com")]
-    pub url: String,
+    /// Solana cluster to work with: mainnet, devnet, localnet or RPC endpoint URL
+    #[structopt(long, short, default_value = "devnet")]
+    pub url: Cluster,
 
-    /// Flash Loan Reserve to work with.
+    /// Flash Loan Reserve to work with. Defaults to the cluster's wrapped SOL reserve.
     #[structopt(long, short)]
-    pub reserve: Pubkey,
+    pub reserve: Option<Pubkey>,
+
+    /// Compute unit limit of the transaction. Sized from a simulation when omitted.
+    #[structopt(long)]
+    pub compute_unit_limit: Option<u32>,
+
+    /// Priority fee in micro-lamports per compute unit.
+    #[structopt(long)]
+    pub compute_unit_price: Option<u64>,
 }
 
 #[derive(FromStr)]
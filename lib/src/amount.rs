@@ -0,0 +1,147 @@
+//! Conversions between token amounts in base units and human readable (UI) amounts
+//!
+//! All amounts in this SDK are in base units of the mint (e.g. lamports). A UI amount is the same
+//! value in whole tokens, e.g. `"10.5"` SOL is `10_500_000_000` lamports for a mint with 9 decimals.
+
+use std::convert::TryFrom;
+
+use crate::error::{FlashProgramError, FlashSdkError};
+use crate::math::{scaled_digits, Decimal, ParseDecimalError};
+use crate::types::ReserveLiquidity;
+
+/// Converts `ui_amount`, e.g. `"10.5"`, into base units of a mint with `decimals`.
+/// Fails with `InvalidAmount` when it is not a decimal number or has more fractional digits than
+/// the mint supports and with `MathOverflow` when it does not fit into u64.
+pub fn ui_amount_to_amount(ui_amount: &str, decimals: u8) -> Result<u64, FlashSdkError> {
+    let overflow = || FlashSdkError::FlashError(FlashProgramError::MathOverflow);
+    let digits = scaled_digits(ui_amount, decimals as usize).map_err(|err| match err {
+        ParseDecimalError::Overflow => overflow(),
+        ParseDecimalError::Invalid | ParseDecimalError::TooManyFractionDigits => {
+            FlashSdkError::InvalidAmount
+        }
+    })?;
+    digits.parse().map_err(|_| overflow())
+}
+
+/// Formats `amount` in base units of a mint with `decimals` as a UI amount without trailing zeros
+pub fn amount_to_ui_amount(amount: u64, decimals: u8) -> String {
+    format_ui_amount(Decimal::from(amount), decimals)
+}
+
+/// Formats `amount` in base units of a mint with `decimals`, which may have a fractional part
+/// (e.g. a fee before rounding), as a UI amount. The result is exact and has no trailing zeros.
+pub fn format_ui_amount(amount: Decimal, decimals: u8) -> String {
+    let amount = amount.to_string();
+    let (integer, fraction) = amount
+        .split_once('.')
+        .expect("Decimal is displayed with a fractional part");
+    let digits = format!("{}{}", integer.trim_start_matches('0'), fraction);
+    let fraction_len = fraction.len() + decimals as usize;
+
+    let (integer, fraction) = if digits.len() > fraction_len {
+        digits.split_at(digits.len() - fraction_len)
+    } else {
+        ("", digits.as_str())
+    };
+    let leading_zeros = fraction_len.saturating_sub(fraction.len());
+    let fraction = fraction.trim_end_matches('0');
+
+    match (integer.is_empty(), fraction.is_empty()) {
+        (true, true) => "0".to_string(),
+        (false, true) => integer.to_string(),
+        (true, false) => format!("0.{}{}", "0".repeat(leading_zeros), fraction),
+        (false, false) => format!("{}.{}", integer, fraction),
+    }
+}
+
+impl ReserveLiquidity {
+    /// Liquidity mint decimals, which are a u8 of the mint. A reserve from
+    /// [unpack_reserve](../fn.unpack_reserve.html) always has them in range, a larger value set
+    /// by hand saturates at `u8::MAX`.
+    pub fn decimals(&self) -> u8 {
+        u8::try_from(self.mint_decimals).unwrap_or(u8::MAX)
+    }
+
+    /// Converts `ui_amount` of the liquidity mint into base units
+    pub fn ui_amount_to_amount(&self, ui_amount: &str) -> Result<u64, FlashSdkError> {
+        ui_amount_to_amount(ui_amount, self.decimals())
+    }
+
+    /// Formats `amount` in base units of the liquidity mint as a UI amount
+    pub fn amount_to_ui_amount(&self, amount: u64) -> String {
+        amount_to_ui_amount(amount, self.decimals())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::{Rate, TryMul};
+
+    #[test]
+    fn parse_ui_amount() {
+        assert_eq!(ui_amount_to_amount("10", 9), Ok(10_000_000_000));
+        assert_eq!(ui_amount_to_amount("10.5", 9), Ok(10_500_000_000));
+        assert_eq!(ui_amount_to_amount(".5", 6), Ok(500_000));
+        assert_eq!(ui_amount_to_amount("1.", 0), Ok(1));
+        assert_eq!(ui_amount_to_amount("0.000000001", 9), Ok(1));
+        assert_eq!(ui_amount_to_amount("1.2000", 1), Ok(12));
+        assert_eq!(ui_amount_to_amount("0", 255), Ok(0));
+        assert_eq!(
+            ui_amount_to_amount("18446744073.709551615", 9),
+            Ok(u64::MAX)
+        );
+
+        for invalid in ["", ".", "1.2.3", "-1", "+1", "1e9", " 1", "0.0000000001"].iter() {
+            assert_eq!(
+                ui_amount_to_amount(invalid, 9),
+                Err(FlashSdkError::InvalidAmount),
+                "{}",
+                invalid
+            );
+        }
+        for overflow in ["18446744073.709551616", "18446744074", "1"].iter() {
+            let decimals = if *overflow == "1" { 20 } else { 9 };
+            assert_eq!(
+                ui_amount_to_amount(overflow, decimals),
+                Err(FlashSdkError::FlashError(FlashProgramError::MathOverflow)),
+                "{}",
+                overflow
+            );
+        }
+    }
+
+    #[test]
+    fn format_amounts() {
+        assert_eq!(amount_to_ui_amount(0, 9), "0");
+        assert_eq!(amount_to_ui_amount(1_500_000_000, 9), "1.5");
+        assert_eq!(amount_to_ui_amount(1, 9), "0.000000001");
+        assert_eq!(amount_to_ui_amount(42, 0), "42");
+        assert_eq!(amount_to_ui_amount(1_000, 2), "10");
+        assert_eq!(amount_to_ui_amount(u64::MAX, 9), "18446744073.709551615");
+        assert_eq!(
+            amount_to_ui_amount(1, 30),
+            "0.000000000000000000000000000001"
+        );
+        // The most decimals a mint can have
+        assert_eq!(
+            amount_to_ui_amount(u64::MAX, u8::MAX),
+            format!("0.{}18446744073709551615", "0".repeat(235))
+        );
+
+        // 0.3% fee of 1001 lamports before rounding
+        let fee = Decimal::from(1001u64)
+            .try_mul(Rate::from_scaled_val(3_000_000_000_000_000))
+            .unwrap();
+        assert_eq!(format_ui_amount(fee, 9), "0.000000003003");
+        assert_eq!(format_ui_amount(fee, 0), "3.003");
+    }
+
+    #[test]
+    fn round_trip() {
+        for (amount, decimals) in [(0, 0), (1, 6), (123_456_789, 3), (u64::MAX, 19)].iter() {
+            let ui_amount = amount_to_ui_amount(*amount, *decimals);
+            assert_eq!(ui_amount_to_amount(&ui_amount, *decimals), Ok(*amount));
+        }
+    }
+}
@@ -0,0 +1,584 @@
+//! Builder of complete flash borrow/repay instruction sequences
+
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use solana_program::system_instruction;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token::native_mint;
+
+use crate::error::FlashSdkError;
+use crate::fetcher::AccountFetcher;
+use crate::flash_loan_fee;
+use crate::instruction::{flash_borrow_with_token_program, flash_repay_with_token_program};
+use crate::token::token_program_for_mint;
+use crate::types::Reserve;
+
+/// `FlashBorrow` and `FlashRepay` instructions together with the instructions which must surround
+/// them in the transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlashLoanInstructions {
+    /// Instructions to put before `borrow`
+    pub setup: Vec<Instruction>,
+    /// `FlashBorrow` instruction
+    pub borrow: Instruction,
+    /// `FlashRepay` instruction
+    pub repay: Instruction,
+    /// Instructions to put after `repay`
+    pub cleanup: Vec<Instruction>,
+}
+
+impl FlashLoanInstructions {
+    /// Returns all instructions in transaction order with `instructions` which use the borrowed
+    /// liquidity placed between `borrow` and `repay`.
+    pub fn with_instructions(
+        self,
+        instructions: impl IntoIterator<Item = Instruction>,
+    ) -> Vec<Instruction> {
+        let mut result = self.setup;
+        result.push(self.borrow);
+        result.extend(instructions);
+        result.push(self.repay);
+        result.extend(self.cleanup);
+        result
+    }
+}
+
+/// Builds a flash borrow/repay pair for a reserve.
+///
+/// The user's liquidity account is either set explicitly or derived as the authority's associated
+/// token account for the reserve liquidity mint, see
+/// [associated_token_account](#method.associated_token_account). A missing associated token account
+/// is created with an idempotent instruction. [build_with_fetcher](#method.build_with_fetcher)
+/// checks whether it exists first and skips the creation if it does.
+///
+/// When the reserve liquidity mint is the native mint (wrapped SOL), the flash loan fee is
+/// pre-funded in lamports by the payer and wrapped with `SyncNative`. Without an explicit
+/// liquidity account the authority's wrapped SOL associated token account is always used. If it
+/// does not exist yet, the SDK creates it and closes it after the repay, returning its lamports
+/// to the authority. An existing account is left as it is. Only
+/// [build_with_fetcher](#method.build_with_fetcher) can tell the two apart, so
+/// [build](#method.build) fails with `MissingAccountFetcher` in this case.
+///
+/// Token-2022 reserves need the token program set with [token_program](#method.token_program)
+/// unless it is inferred from the mint account by [build_with_fetcher](#method.build_with_fetcher).
+/// Otherwise SPL Token is assumed. For Token-2022 the liquidity mint is passed to the borrow and
+/// the repay so that mints with a transfer fee can be transferred.
+#[derive(Clone, Debug)]
+pub struct FlashLoanBuilder {
+    program_id: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve: Reserve,
+    amount: u64,
+    authority: Pubkey,
+    payer: Pubkey,
+    liquidity_account: Option<Pubkey>,
+    associated_token_account: bool,
+    token_program: Option<Pubkey>,
+}
+
+impl FlashLoanBuilder {
+    /// Creates a builder for flash borrowing `amount` from `reserve`.
+    /// `authority` owns the user's liquidity account and signs the repay. It also pays for the
+    /// transaction unless [payer](#method.payer) is set.
+    pub fn new(
+        program_id: Pubkey,
+        reserve_pubkey: Pubkey,
+        reserve: &Reserve,
+        amount: u64,
+        authority: Pubkey,
+    ) -> Self {
+        Self {
+            program_id,
+            reserve_pubkey,
+            reserve: *reserve,
+            amount,
+            authority,
+            payer: authority,
+            liquidity_account: None,
+            associated_token_account: false,
+            token_program: None,
+        }
+    }
+
+    /// Account funding the account creation and the wrapped SOL fee
+    pub fn payer(mut self, payer: Pubkey) -> Self {
+        self.payer = payer;
+        self
+    }
+
+    /// User's token account to receive borrowed liquidity and repay from
+    pub fn liquidity_account(mut self, liquidity_account: Pubkey) -> Self {
+        self.liquidity_account = Some(liquidity_account);
+        self
+    }
+
+    /// Use the authority's associated token account for the reserve liquidity mint as the user's
+    /// liquidity account
+    pub fn associated_token_account(mut self) -> Self {
+        self.associated_token_account = true;
+        self
+    }
+
+    /// Token program owning the reserve liquidity mint: SPL Token or Token-2022
+    pub fn token_program(mut self, token_program: Pubkey) -> Self {
+        self.token_program = Some(token_program);
+        self
+    }
+
+    /// Builds the instructions
+    pub fn build(&self) -> Result<FlashLoanInstructions, FlashSdkError> {
+        self.build_instructions(None)
+    }
+
+    /// Builds the instructions, using `fetcher` to check whether the associated token account
+    /// already exists and to infer the token program from the mint account if it is not set
+    pub fn build_with_fetcher(
+        &self,
+        fetcher: &dyn AccountFetcher,
+    ) -> Result<FlashLoanInstructions, FlashSdkError> {
+        self.build_instructions(Some(fetcher))
+    }
+
+    fn build_instructions(
+        &self,
+        fetcher: Option<&dyn AccountFetcher>,
+    ) -> Result<FlashLoanInstructions, FlashSdkError> {
+        let mint = self.reserve.liquidity.mint_pubkey;
+        let is_native = mint == native_mint::id();
+        let token_program = match (self.token_program, fetcher) {
+            (Some(token_program), _) => token_program,
+            (None, Some(fetcher)) => token_program_for_mint(fetcher, &mint)?,
+            (None, None) => spl_token::id(),
+        };
+        let mut setup = vec![];
+        let mut cleanup = vec![];
+
+        let liquidity_account = match self.liquidity_account {
+            Some(liquidity_account) => liquidity_account,
+            None if is_native || self.associated_token_account => {
+                let associated_token_account = get_associated_token_address_with_program_id(
+                    &self.authority,
+                    &mint,
+                    &token_program,
+                );
+                let exists = match fetcher {
+                    Some(fetcher) => fetcher.get_account(&associated_token_account)?.is_some(),
+                    // Closing an account which existed before would unwrap the authority's SOL
+                    None if is_native => return Err(FlashSdkError::MissingAccountFetcher),
+                    None => false,
+                };
+                if !exists {
+                    setup.push(create_associated_token_account_idempotent(
+                        &self.payer,
+                        &self.authority,
+                        &mint,
+                        &token_program,
+                    ));
+                    if is_native {
+                        cleanup.push(
+                            spl_token_2022::instruction::close_account(
+                                &token_program,
+                                &associated_token_account,
+                                &self.authority,
+                                &self.authority,
+                                &[],
+                            )
+                            .map_err(|_| FlashSdkError::UnsupportedTokenProgram(token_program))?,
+                        );
+                    }
+                }
+                associated_token_account
+            }
+            None => return Err(FlashSdkError::MissingLiquidityAccount),
+        };
+
+        if is_native {
+            let fee = flash_loan_fee(&self.reserve, self.amount)?;
+            if fee > 0 {
+                setup.push(system_instruction::transfer(
+                    &self.payer,
+                    &liquidity_account,
+                    fee,
+                ));
+                setup.push(
+                    spl_token_2022::instruction::sync_native(&token_program, &liquidity_account)
+                        .map_err(|_| FlashSdkError::UnsupportedTokenProgram(token_program))?,
+                );
+            }
+        }
+
+        // Token-2022 mints may carry a transfer fee, which requires `TransferChecked`
+        let liquidity_mint = if token_program == spl_token_2022::id() {
+            Some(mint)
+        } else {
+            None
+        };
+        Ok(FlashLoanInstructions {
+            setup,
+            borrow: flash_borrow_with_token_program(
+                self.program_id,
+                self.amount,
+                self.reserve.liquidity.supply_pubkey,
+                liquidity_account,
+                self.reserve_pubkey,
+                self.reserve.lending_market,
+                token_program,
+                liquidity_mint,
+            ),
+            repay: flash_repay_with_token_program(
+                self.program_id,
+                self.amount,
+                liquidity_account,
+                self.reserve.liquidity.supply_pubkey,
+                self.reserve.config.fee_receiver,
+                self.reserve_pubkey,
+                self.reserve.lending_market,
+                self.authority,
+                token_program,
+                liquidity_mint,
+            ),
+            cleanup,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use solana_program::clock::Clock;
+    use solana_sdk::account::Account;
+    use solana_sdk::signature::Signer;
+    use spl_associated_token_account::get_associated_token_address;
+
+    use super::*;
+    use crate::quote::FlashLoanQuote;
+    use crate::testing::{FlashLoanFixture, FlashLoanFixtureConfig, ReserveBuilder};
+    use crate::FLASH_LOAN_ID;
+
+    #[test]
+    fn build_token_flash_loan() {
+        let reserve = ReserveBuilder::new().available_liquidity(1_000_000).build();
+        let reserve_pubkey = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let liquidity_account = Pubkey::new_unique();
+        let builder =
+            FlashLoanBuilder::new(FLASH_LOAN_ID, reserve_pubkey, &reserve, 10_000, authority);
+
+        assert_eq!(builder.build(), Err(FlashSdkError::MissingLiquidityAccount));
+
+        let instructions = builder
+            .liquidity_account(liquidity_account)
+            .build()
+            .unwrap();
+        assert!(instructions.setup.is_empty());
+        assert!(instructions.cleanup.is_empty());
+        assert_eq!(instructions.borrow.accounts[1].pubkey, liquidity_account);
+        assert_eq!(instructions.repay.accounts[0].pubkey, liquidity_account);
+        assert_eq!(instructions.repay.accounts[5].pubkey, authority);
+
+        let other = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let all = instructions.clone().with_instructions(vec![other.clone()]);
+        assert_eq!(all, vec![instructions.borrow, other, instructions.repay]);
+    }
+
+    #[test]
+    fn build_wrapped_sol_flash_loan() {
+        let reserve = ReserveBuilder::new()
+            .mint(native_mint::id())
+            .available_liquidity(1_000_000_000)
+            .build();
+        let authority = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let wrapped_sol_account = get_associated_token_address(&authority, &native_mint::id());
+        let builder = FlashLoanBuilder::new(
+            FLASH_LOAN_ID,
+            Pubkey::new_unique(),
+            &reserve,
+            1_000_000,
+            authority,
+        )
+        .payer(payer);
+        assert_eq!(builder.build(), Err(FlashSdkError::MissingAccountFetcher));
+
+        let accounts = vec![(
+            native_mint::id(),
+            Account {
+                owner: spl_token::id(),
+                ..Account::default()
+            },
+        )]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+        let instructions = builder.build_with_fetcher(&accounts).unwrap();
+
+        assert_eq!(instructions.setup.len(), 3);
+        assert_eq!(
+            instructions.setup[1],
+            system_instruction::transfer(&payer, &wrapped_sol_account, 3_000)
+        );
+        // Lamports of the temporary account go back to the authority, not the payer
+        assert_eq!(
+            instructions.cleanup,
+            vec![spl_token::instruction::close_account(
+                &spl_token::id(),
+                &wrapped_sol_account,
+                &authority,
+                &authority,
+                &[]
+            )
+            .unwrap()]
+        );
+        assert_eq!(instructions.borrow.accounts[1].pubkey, wrapped_sol_account);
+        assert_eq!(instructions.repay.accounts[0].pubkey, wrapped_sol_account);
+
+        let token_program = Pubkey::new_unique();
+        assert_eq!(
+            builder
+                .token_program(token_program)
+                .build_with_fetcher(&accounts),
+            Err(FlashSdkError::UnsupportedTokenProgram(token_program))
+        );
+    }
+
+    #[test]
+    fn build_with_associated_token_account() {
+        let mint = Pubkey::new_unique();
+        let reserve = ReserveBuilder::new()
+            .mint(mint)
+            .available_liquidity(1_000_000)
+            .build();
+        let authority = Pubkey::new_unique();
+        let associated_token_account = get_associated_token_address(&authority, &mint);
+        let builder = FlashLoanBuilder::new(
+            FLASH_LOAN_ID,
+            Pubkey::new_unique(),
+            &reserve,
+            10_000,
+            authority,
+        )
+        .associated_token_account();
+
+        let mut accounts = HashMap::new();
+        accounts.insert(
+            mint,
+            Account {
+                owner: spl_token::id(),
+                ..Account::default()
+            },
+        );
+        let instructions = builder.build_with_fetcher(&accounts).unwrap();
+        assert_eq!(
+            instructions.setup,
+            vec![create_associated_token_account_idempotent(
+                &authority,
+                &authority,
+                &mint,
+                &spl_token::id()
+            )]
+        );
+        assert!(instructions.cleanup.is_empty());
+        assert_eq!(
+            instructions.borrow.accounts[1].pubkey,
+            associated_token_account
+        );
+        assert_eq!(builder.build().unwrap(), instructions);
+
+        accounts.insert(associated_token_account, Account::default());
+        let instructions = builder.build_with_fetcher(&accounts).unwrap();
+        assert!(instructions.setup.is_empty());
+        assert_eq!(
+            instructions.repay.accounts[0].pubkey,
+            associated_token_account
+        );
+    }
+
+    #[test]
+    fn keep_existing_wrapped_sol_account() {
+        let reserve = ReserveBuilder::new()
+            .mint(native_mint::id())
+            .available_liquidity(1_000_000_000)
+            .build();
+        let authority = Pubkey::new_unique();
+        let wrapped_sol_account = get_associated_token_address(&authority, &native_mint::id());
+        let accounts = vec![
+            (wrapped_sol_account, Account::default()),
+            (
+                native_mint::id(),
+                Account {
+                    owner: spl_token::id(),
+                    ..Account::default()
+                },
+            ),
+        ]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+        let instructions = FlashLoanBuilder::new(
+            FLASH_LOAN_ID,
+            Pubkey::new_unique(),
+            &reserve,
+            1_000_000,
+            authority,
+        )
+        .build_with_fetcher(&accounts)
+        .unwrap();
+
+        assert_eq!(instructions.setup.len(), 2);
+        assert!(instructions.cleanup.is_empty());
+    }
+
+    #[test]
+    fn build_token_2022_flash_loan() {
+        let mint = Pubkey::new_unique();
+        let reserve = ReserveBuilder::new()
+            .mint(mint)
+            .available_liquidity(1_000_000)
+            .build();
+        let authority = Pubkey::new_unique();
+        let associated_token_account =
+            get_associated_token_address_with_program_id(&authority, &mint, &spl_token_2022::id());
+        let builder = FlashLoanBuilder::new(
+            FLASH_LOAN_ID,
+            Pubkey::new_unique(),
+            &reserve,
+            10_000,
+            authority,
+        )
+        .associated_token_account();
+
+        let accounts = vec![(
+            mint,
+            Account {
+                owner: spl_token_2022::id(),
+                ..Account::default()
+            },
+        )]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+        let instructions = builder.build_with_fetcher(&accounts).unwrap();
+        assert_eq!(
+            instructions.setup,
+            vec![create_associated_token_account_idempotent(
+                &authority,
+                &authority,
+                &mint,
+                &spl_token_2022::id()
+            )]
+        );
+        assert_eq!(
+            instructions.borrow.accounts[1].pubkey,
+            associated_token_account
+        );
+        assert_eq!(instructions.borrow.accounts[6].pubkey, spl_token_2022::id());
+        assert_eq!(instructions.borrow.accounts[7].pubkey, mint);
+        assert_eq!(instructions.repay.accounts[7].pubkey, spl_token_2022::id());
+        assert_eq!(instructions.repay.accounts[8].pubkey, mint);
+        assert_eq!(
+            builder.token_program(spl_token_2022::id()).build().unwrap(),
+            instructions
+        );
+    }
+
+    #[tokio::test]
+    async fn transfer_fee_flash_loan() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig {
+            transfer_fee_bps: Some(100),
+            ..FlashLoanFixtureConfig::default()
+        })
+        .await;
+        let reserve = fixture.reserve().await;
+        let mint = fixture
+            .context
+            .banks_client
+            .get_account(fixture.mint)
+            .await
+            .unwrap()
+            .unwrap();
+        let clock = fixture
+            .context
+            .banks_client
+            .get_sysvar::<Clock>()
+            .await
+            .unwrap();
+        let amount = 5_000_000_000;
+        let quote =
+            FlashLoanQuote::with_transfer_fee(&reserve, amount, &mint.data, clock.epoch).unwrap();
+        let accounts = vec![(fixture.mint, mint)]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        // The token program is inferred from the mint account
+        let instructions = FlashLoanBuilder::new(
+            fixture.program_id,
+            fixture.reserve_pubkey,
+            &reserve,
+            amount,
+            fixture.user.pubkey(),
+        )
+        .liquidity_account(fixture.user_liquidity)
+        .build_with_fetcher(&accounts)
+        .unwrap();
+        assert_eq!(
+            instructions.borrow.accounts.last().unwrap().pubkey,
+            fixture.mint
+        );
+        assert_eq!(
+            instructions.repay.accounts.last().unwrap().pubkey,
+            fixture.mint
+        );
+        fixture
+            .process_transaction(&instructions.with_instructions(vec![]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fixture.token_balance(fixture.user_liquidity).await,
+            10_000_000_000 - quote.top_up_amount
+        );
+        assert_eq!(fixture.token_balance(fixture.fee_receiver).await, 2_970_000);
+    }
+
+    #[tokio::test]
+    async fn wrapped_sol_flash_loan() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig {
+            native_mint: true,
+            ..FlashLoanFixtureConfig::default()
+        })
+        .await;
+        let reserve = fixture.reserve().await;
+        let amount = 5_000_000_000;
+        let fee = flash_loan_fee(&reserve, amount).unwrap();
+        let wrapped_sol_account =
+            get_associated_token_address(&fixture.user.pubkey(), &native_mint::id());
+
+        let instructions = FlashLoanBuilder::new(
+            fixture.program_id,
+            fixture.reserve_pubkey,
+            &reserve,
+            amount,
+            fixture.user.pubkey(),
+        )
+        .payer(fixture.context.payer.pubkey())
+        .token_program(spl_token::id())
+        // The wrapped SOL account does not exist yet
+        .build_with_fetcher(&HashMap::new())
+        .unwrap()
+        .with_instructions(vec![]);
+        fixture.process_transaction(&instructions).await.unwrap();
+
+        assert_eq!(
+            fixture.reserve().await.liquidity.available_amount,
+            reserve.liquidity.available_amount + fee - fee / 5
+        );
+        assert_eq!(fixture.token_balance(fixture.fee_receiver).await, fee / 5);
+        assert!(fixture
+            .context
+            .banks_client
+            .get_account(wrapped_sol_account)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
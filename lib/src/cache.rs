@@ -0,0 +1,382 @@
+//! Cache of reserves to save RPC requests
+//!
+//! [ReserveCache](struct.ReserveCache.html) keeps reserves loaded with one
+//! `getMultipleAccounts` request per up to 100 reserves and serves fee and liquidity queries
+//! until they are older than the configured [MaxAge](enum.MaxAge.html).
+//!
+//! The age in slots is counted from the RPC context slot the reserve was loaded at to the
+//! current slot, estimated from the latest context slot seen by the cache and the time passed
+//! since. A reserve with an older `last_update` than the cached one, e.g. from a lagging RPC
+//! node, never replaces it, but still counts as a newer load of the cached reserve.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::MAX_MULTIPLE_ACCOUNTS;
+use solana_program::clock::{Slot, DEFAULT_MS_PER_SLOT};
+use solana_program::pubkey::Pubkey;
+
+use crate::error::FlashSdkError;
+use crate::types::Reserve;
+use crate::{available_liquidity, flash_loan_fee, unpack_reserve};
+
+/// How long a cached reserve is used before it is loaded again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaxAge {
+    /// Number of slots since the RPC context slot the reserve was loaded at
+    Slots(Slot),
+    /// Time since the reserve was loaded
+    Duration(Duration),
+}
+
+/// Reserve with the time it was loaded at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CachedReserve {
+    /// Cached reserve
+    pub reserve: Reserve,
+    /// RPC context slot the reserve was loaded at
+    pub slot: Slot,
+    /// When the reserve was loaded
+    pub loaded_at: Instant,
+}
+
+/// Reserves of a Flash Loan program keyed by pubkey
+#[derive(Clone, Debug)]
+pub struct ReserveCache {
+    program_id: Pubkey,
+    max_age: MaxAge,
+    reserves: HashMap<Pubkey, CachedReserve>,
+    /// Latest RPC context slot and when it was seen
+    latest_slot: Option<(Slot, Instant)>,
+}
+
+impl ReserveCache {
+    /// Creates an empty cache of reserves of `program_id`
+    pub fn new(program_id: Pubkey, max_age: MaxAge) -> Self {
+        Self {
+            program_id,
+            max_age,
+            reserves: HashMap::new(),
+            latest_slot: None,
+        }
+    }
+
+    /// Adds `reserve` loaded at `slot` by other means, e.g. a
+    /// [ReserveWatcher](../watcher/struct.ReserveWatcher.html)
+    pub fn insert(&mut self, reserve_pubkey: Pubkey, reserve: Reserve, slot: Slot) {
+        let now = Instant::now();
+        match self.latest_slot {
+            Some((latest_slot, _)) if latest_slot >= slot => {}
+            _ => self.latest_slot = Some((slot, now)),
+        }
+
+        match self.reserves.get_mut(&reserve_pubkey) {
+            // Keep the newer data, which was still current at `slot`
+            Some(cached) if cached.reserve.last_update > reserve.last_update => {
+                cached.slot = cached.slot.max(slot);
+                cached.loaded_at = now;
+            }
+            _ => {
+                self.reserves.insert(
+                    reserve_pubkey,
+                    CachedReserve {
+                        reserve,
+                        slot,
+                        loaded_at: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns the cached reserve regardless of its age
+    pub fn get(&self, reserve_pubkey: &Pubkey) -> Option<&CachedReserve> {
+        self.reserves.get(reserve_pubkey)
+    }
+
+    /// Removes the reserve from the cache
+    pub fn remove(&mut self, reserve_pubkey: &Pubkey) -> Option<CachedReserve> {
+        self.reserves.remove(reserve_pubkey)
+    }
+
+    /// Current slot estimated from the latest RPC context slot
+    pub fn estimated_slot(&self) -> Option<Slot> {
+        self.latest_slot.map(|(slot, seen_at)| {
+            slot + seen_at.elapsed().as_millis() as u64 / DEFAULT_MS_PER_SLOT
+        })
+    }
+
+    /// Whether the reserve is cached and not older than the max age
+    pub fn is_fresh(&self, reserve_pubkey: &Pubkey) -> bool {
+        let cached = match self.reserves.get(reserve_pubkey) {
+            Some(cached) => cached,
+            None => return false,
+        };
+        match self.max_age {
+            MaxAge::Slots(max_slots) => match self.estimated_slot() {
+                Some(slot) => slot.saturating_sub(cached.slot) <= max_slots,
+                None => false,
+            },
+            MaxAge::Duration(max_duration) => cached.loaded_at.elapsed() < max_duration,
+        }
+    }
+
+    /// Loads all `reserve_pubkeys` with as few RPC requests as possible. Reserves which do not
+    /// exist are removed from the cache. Returns the first error of an account which is not a
+    /// valid reserve after caching all valid ones.
+    pub fn refresh(
+        &mut self,
+        rpc_client: &RpcClient,
+        reserve_pubkeys: &[Pubkey],
+    ) -> Result<(), FlashSdkError> {
+        let mut result = Ok(());
+        for pubkeys in reserve_pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let response = rpc_client
+                .get_multiple_accounts_with_commitment(pubkeys, rpc_client.commitment())
+                .map_err(|_| FlashSdkError::RpcError)?;
+
+            for (pubkey, account) in pubkeys.iter().zip(response.value) {
+                let account = match account {
+                    Some(account) => account,
+                    None => {
+                        self.reserves.remove(pubkey);
+                        continue;
+                    }
+                };
+                match unpack_reserve(&self.program_id, &account.owner, &account.data) {
+                    Ok(reserve) => self.insert(*pubkey, reserve, response.context.slot),
+                    Err(err) => {
+                        if result.is_ok() {
+                            result = Err(err);
+                        }
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Loads those of `reserve_pubkeys` which are missing or older than the max age
+    pub fn refresh_stale(
+        &mut self,
+        rpc_client: &RpcClient,
+        reserve_pubkeys: &[Pubkey],
+    ) -> Result<(), FlashSdkError> {
+        let stale = reserve_pubkeys
+            .iter()
+            .filter(|pubkey| !self.is_fresh(pubkey))
+            .copied()
+            .collect::<Vec<_>>();
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        self.refresh(rpc_client, &stale)
+    }
+
+    /// Returns the reserve, loading it if it is missing or older than the max age.
+    /// Fails with `ReserveNotFound` if the reserve account does not exist.
+    pub fn reserve(
+        &mut self,
+        rpc_client: &RpcClient,
+        reserve_pubkey: &Pubkey,
+    ) -> Result<&Reserve, FlashSdkError> {
+        self.refresh_stale(rpc_client, &[*reserve_pubkey])?;
+
+        self.reserves
+            .get(reserve_pubkey)
+            .map(|cached| &cached.reserve)
+            .ok_or(FlashSdkError::ReserveNotFound(*reserve_pubkey))
+    }
+
+    /// [flash_loan_fee](../fn.flash_loan_fee.html) of the cached reserve
+    pub fn flash_loan_fee(
+        &mut self,
+        rpc_client: &RpcClient,
+        reserve_pubkey: &Pubkey,
+        borrow_amount: u64,
+    ) -> Result<u64, FlashSdkError> {
+        flash_loan_fee(self.reserve(rpc_client, reserve_pubkey)?, borrow_amount)
+    }
+
+    /// [available_liquidity](../fn.available_liquidity.html) of the cached reserve
+    pub fn available_liquidity(
+        &mut self,
+        rpc_client: &RpcClient,
+        reserve_pubkey: &Pubkey,
+    ) -> Result<u64, FlashSdkError> {
+        Ok(available_liquidity(
+            self.reserve(rpc_client, reserve_pubkey)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+    use solana_account_decoder::{UiAccount, UiAccountEncoding};
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::account::Account;
+
+    use super::*;
+    use crate::testing::ReserveBuilder;
+
+    /// Client answering one `getMultipleAccounts` request with `accounts` at `slot`
+    fn rpc_client(slot: Slot, accounts: &[Option<Account>]) -> RpcClient {
+        let accounts = accounts
+            .iter()
+            .map(|account| match account {
+                Some(account) => json!(UiAccount::encode(
+                    &Pubkey::new_unique(),
+                    account,
+                    UiAccountEncoding::Base64,
+                    None,
+                    None,
+                )),
+                None => Value::Null,
+            })
+            .collect::<Vec<_>>();
+        let mut mocks = HashMap::new();
+        mocks.insert(
+            RpcRequest::GetMultipleAccounts,
+            json!({"context": {"slot": slot}, "value": accounts}),
+        );
+        RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks)
+    }
+
+    #[test]
+    fn serve_from_cache() {
+        let sol = ReserveBuilder::new().available_liquidity(1_000_000_000_000);
+        let usdc = ReserveBuilder::new()
+            .available_liquidity(5_000_000)
+            .fee_bps(50);
+        let program_id = sol.account().owner;
+        let (sol_pubkey, usdc_pubkey) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut cache = ReserveCache::new(program_id, MaxAge::Slots(10));
+
+        cache
+            .refresh(
+                &rpc_client(100, &[Some(sol.account()), Some(usdc.account())]),
+                &[sol_pubkey, usdc_pubkey],
+            )
+            .unwrap();
+        assert_eq!(cache.get(&sol_pubkey).unwrap().slot, 100);
+        assert_eq!(cache.estimated_slot(), Some(100));
+
+        // No more RPC responses, so these are served from the cache
+        let rpc_client = rpc_client(0, &[]);
+        assert_eq!(
+            cache.flash_loan_fee(&rpc_client, &sol_pubkey, 1_000_000_000),
+            Ok(3_000_000)
+        );
+        assert_eq!(
+            cache.flash_loan_fee(&rpc_client, &usdc_pubkey, 1_000_000),
+            Ok(5_000)
+        );
+        assert_eq!(
+            cache.available_liquidity(&rpc_client, &usdc_pubkey),
+            Ok(5_000_000)
+        );
+    }
+
+    #[test]
+    fn refresh_stale_reserves() {
+        let builder = ReserveBuilder::new()
+            .available_liquidity(100)
+            .last_update(5);
+        let program_id = builder.account().owner;
+        let (old_pubkey, new_pubkey) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut cache = ReserveCache::new(program_id, MaxAge::Slots(10));
+        cache.insert(old_pubkey, builder.build(), 100);
+        assert!(cache.is_fresh(&old_pubkey));
+        assert!(!cache.is_fresh(&new_pubkey));
+
+        cache.insert(new_pubkey, builder.build(), 111);
+        assert!(!cache.is_fresh(&old_pubkey));
+        assert!(cache.is_fresh(&new_pubkey));
+
+        // Only the stale reserve is requested
+        let updated = builder.clone().available_liquidity(200).last_update(110);
+        cache
+            .refresh_stale(
+                &rpc_client(112, &[Some(updated.account())]),
+                &[old_pubkey, new_pubkey],
+            )
+            .unwrap();
+        assert_eq!(cache.get(&old_pubkey).unwrap().reserve, updated.build());
+        assert!(cache.is_fresh(&old_pubkey));
+
+        let mut cache = ReserveCache::new(program_id, MaxAge::Duration(Duration::ZERO));
+        cache.insert(old_pubkey, builder.build(), 100);
+        assert!(!cache.is_fresh(&old_pubkey));
+        let mut cache = ReserveCache::new(program_id, MaxAge::Duration(Duration::from_secs(60)));
+        cache.insert(old_pubkey, builder.build(), 100);
+        assert!(cache.is_fresh(&old_pubkey));
+    }
+
+    #[test]
+    fn keep_newer_reserve() {
+        let builder = ReserveBuilder::new()
+            .available_liquidity(100)
+            .last_update(50);
+        let program_id = builder.account().owner;
+        let pubkey = Pubkey::new_unique();
+        let mut cache = ReserveCache::new(program_id, MaxAge::Slots(10));
+        cache.insert(pubkey, builder.build(), 60);
+        let loaded_at = cache.get(&pubkey).unwrap().loaded_at;
+
+        // A lagging node returns the state before the last update
+        let lagging = builder.clone().available_liquidity(50).last_update(40);
+        cache
+            .refresh(&rpc_client(71, &[Some(lagging.account())]), &[pubkey])
+            .unwrap();
+        let cached = cache.get(&pubkey).unwrap();
+        assert_eq!(cached.reserve, builder.build());
+        // Still counts as loaded at the newer slot
+        assert_eq!(cached.slot, 71);
+        assert!(cached.loaded_at > loaded_at);
+        assert_eq!(cache.estimated_slot(), Some(71));
+        assert!(cache.is_fresh(&pubkey));
+
+        // Nor is the slot moved back
+        cache.insert(pubkey, lagging.build(), 65);
+        assert_eq!(cache.get(&pubkey).unwrap().slot, 71);
+    }
+
+    #[test]
+    fn refresh_errors() {
+        let builder = ReserveBuilder::new();
+        let program_id = builder.account().owner;
+        let (valid, invalid, missing) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut cache = ReserveCache::new(program_id, MaxAge::Slots(10));
+        cache.insert(missing, builder.build(), 1);
+
+        let mut not_reserve = builder.account();
+        not_reserve.owner = Pubkey::new_unique();
+        assert_eq!(
+            cache.refresh(
+                &rpc_client(2, &[Some(not_reserve), Some(builder.account()), None]),
+                &[invalid, valid, missing],
+            ),
+            Err(FlashSdkError::InvalidReserveOwner)
+        );
+        assert!(cache.get(&valid).is_some());
+        assert!(cache.get(&invalid).is_none());
+        assert!(cache.get(&missing).is_none());
+
+        assert_eq!(
+            cache.reserve(&rpc_client(3, &[None]), &missing),
+            Err(FlashSdkError::ReserveNotFound(missing))
+        );
+        assert_eq!(
+            cache.reserve(&RpcClient::new_mock("fails".to_string()), &missing),
+            Err(FlashSdkError::RpcError)
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Solana clusters with the Flash Loan program deployed
+
+use std::str::FromStr;
+
+use solana_program::{pubkey, pubkey::Pubkey};
+use thiserror::Error;
+use url::Url;
+
+use crate::FLASH_LOAN_ID;
+
+/// Flash Loan reserve known to the SDK
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KnownReserve {
+    /// Symbol of the reserve liquidity mint, e.g. "SOL"
+    pub symbol: &'static str,
+    /// Reserve liquidity mint address
+    pub mint: Pubkey,
+    /// Reserve address
+    pub reserve: Pubkey,
+}
+
+const DEVNET_RESERVES: &[KnownReserve] = &[KnownReserve {
+    symbol: "SOL",
+    mint: spl_token::native_mint::ID,
+    reserve: pubkey!("9Wys2sCHcAGZm3jgSnfP8xyq1ZiK2qthQ4Ki5fSdkqP"),
+}];
+
+/// Solana cluster to work with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cluster {
+    /// Mainnet Beta
+    Mainnet,
+    /// Devnet
+    Devnet,
+    /// Local test validator
+    Localnet,
+    /// Any other RPC endpoint
+    Custom {
+        /// RPC endpoint URL
+        url: String,
+        /// Flash Loan program id deployed there
+        program_id: Pubkey,
+    },
+}
+
+impl Cluster {
+    /// Flash Loan program id
+    pub fn program_id(&self) -> Pubkey {
+        match self {
+            Cluster::Mainnet | Cluster::Devnet | Cluster::Localnet => FLASH_LOAN_ID,
+            Cluster::Custom { program_id, .. } => *program_id,
+        }
+    }
+
+    /// Default RPC endpoint URL
+    pub fn url(&self) -> &str {
+        match self {
+            Cluster::Mainnet => "https://api.mainnet-beta.solana.com",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Localnet => "http://localhost:8899",
+            Cluster::Custom { url, .. } => url,
+        }
+    }
+
+    /// Reserves known to the SDK on this cluster
+    pub fn reserves(&self) -> &'static [KnownReserve] {
+        match self {
+            Cluster::Devnet => DEVNET_RESERVES,
+            Cluster::Mainnet | Cluster::Localnet | Cluster::Custom { .. } => &[],
+        }
+    }
+
+    /// Address of the known reserve for a mint `symbol`, e.g. `Cluster::Devnet.reserve("SOL")`
+    pub fn reserve(&self, symbol: &str) -> Option<Pubkey> {
+        self.reserves()
+            .iter()
+            .find(|known| known.symbol.eq_ignore_ascii_case(symbol))
+            .map(|known| known.reserve)
+    }
+}
+
+/// Cluster is neither a known moniker nor an HTTP(S) URL
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("Unknown cluster {0}, expected mainnet, devnet, localnet or an RPC endpoint URL")]
+pub struct ParseClusterError(pub String);
+
+impl FromStr for Cluster {
+    type Err = ParseClusterError;
+
+    /// Parses a cluster moniker (`mainnet`, `mainnet-beta`, `devnet`, `localnet`, `localhost`)
+    /// or an `http(s)` RPC URL, which becomes a custom cluster with the default program id.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "mainnet" | "mainnet-beta" | "m" => Cluster::Mainnet,
+            "devnet" | "d" => Cluster::Devnet,
+            "localnet" | "localhost" | "l" => Cluster::Localnet,
+            url => match Url::parse(url) {
+                Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {
+                    Cluster::Custom {
+                        url: url.to_string(),
+                        program_id: FLASH_LOAN_ID,
+                    }
+                }
+                _ => return Err(ParseClusterError(url.to_string())),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn known_reserves() {
+        assert_eq!(
+            Cluster::Devnet
+                .reserve("sol")
+                .map(|reserve| reserve.to_string()),
+            Some("9Wys2sCHcAGZm3jgSnfP8xyq1ZiK2qthQ4Ki5fSdkqP".to_string())
+        );
+        assert_eq!(Cluster::Devnet.reserve("USDC"), None);
+        assert_eq!(Cluster::Mainnet.reserve("SOL"), None);
+    }
+
+    #[test]
+    fn parse_cluster() {
+        assert_eq!("devnet".parse(), Ok(Cluster::Devnet));
+        assert_eq!("mainnet-beta".parse(), Ok(Cluster::Mainnet));
+
+        let program_id = Pubkey::new_unique();
+        let custom = Cluster::Custom {
+            url: "http://127.0.0.1:8899".to_string(),
+            program_id,
+        };
+        assert_eq!(custom.url(), "http://127.0.0.1:8899");
+        assert_eq!(custom.program_id(), program_id);
+        assert_eq!(
+            "http://127.0.0.1:8899"
+                .parse::<Cluster>()
+                .unwrap()
+                .program_id(),
+            FLASH_LOAN_ID
+        );
+
+        for invalid in ["devnte", "localhost:8899", "ws://127.0.0.1:8900", ""] {
+            assert_eq!(
+                invalid.parse::<Cluster>(),
+                Err(ParseClusterError(invalid.to_string()))
+            );
+        }
+    }
+}
@@ -3,7 +3,8 @@
 use std::fmt::{Display, Formatter};
 
 use num_derive::FromPrimitive;
-use solana_program::{decode_error::DecodeError, program_error::ProgramError};
+use solana_program::{decode_error::DecodeError, program_error::ProgramError, pubkey::Pubkey};
+use solana_sdk::transaction::TransactionError;
 use thiserror::Error;
 
 /// Errors that may be returned by the Flash Loan program.
@@ -11,6 +12,28 @@ use thiserror::Error;
 pub enum FlashSdkError {
     RpcError,
     DeserializationError,
+    /// Reserve account is not owned by the Flash Loan program
+    InvalidReserveOwner,
+    /// Reserve account does not exist
+    ReserveNotFound(Pubkey),
+    /// Reserve account is not initialized
+    ReserveNotInitialized,
+    /// Reserve layout version is not supported by this SDK
+    UnsupportedReserveVersion(u8),
+    /// User's liquidity token account is not specified and cannot be derived
+    MissingLiquidityAccount,
+    /// Token program is neither SPL Token nor Token-2022
+    UnsupportedTokenProgram(Pubkey),
+    /// Building needs an account fetcher, e.g. to check whether the wrapped SOL account exists
+    MissingAccountFetcher,
+    /// Token amount is not a decimal number or is more precise than the mint allows
+    InvalidAmount,
+    /// Instructions cannot be compiled into a transaction message
+    TransactionCompileError,
+    /// Transaction cannot be signed by the given signers
+    SigningError,
+    /// Transaction failed
+    TransactionError(TransactionError),
     FlashError(FlashProgramError),
 }
 
@@ -19,6 +42,21 @@ impl Display for FlashSdkError {
         match self {
             FlashSdkError::RpcError => f.write_str("RpcError"),
             FlashSdkError::DeserializationError => f.write_str("DeserializationError"),
+            FlashSdkError::InvalidReserveOwner => f.write_str("InvalidReserveOwner"),
+            FlashSdkError::ReserveNotFound(reserve) => write!(f, "ReserveNotFound({})", reserve),
+            FlashSdkError::ReserveNotInitialized => f.write_str("ReserveNotInitialized"),
+            FlashSdkError::UnsupportedReserveVersion(version) => {
+                write!(f, "UnsupportedReserveVersion({})", version)
+            }
+            FlashSdkError::MissingLiquidityAccount => f.write_str("MissingLiquidityAccount"),
+            FlashSdkError::UnsupportedTokenProgram(program_id) => {
+                write!(f, "UnsupportedTokenProgram({})", program_id)
+            }
+            FlashSdkError::MissingAccountFetcher => f.write_str("MissingAccountFetcher"),
+            FlashSdkError::InvalidAmount => f.write_str("InvalidAmount"),
+            FlashSdkError::TransactionCompileError => f.write_str("TransactionCompileError"),
+            FlashSdkError::SigningError => f.write_str("SigningError"),
+            FlashSdkError::TransactionError(err) => write!(f, "TransactionError({})", err),
             FlashSdkError::FlashError(flash_err) => flash_err.fmt(f),
         }
     }
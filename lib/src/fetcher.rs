@@ -0,0 +1,30 @@
+//! Sources of on-chain accounts
+
+use std::collections::HashMap;
+
+use solana_client::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+
+use crate::error::FlashSdkError;
+
+/// Something which can load accounts, e.g. an RPC client or an in-memory snapshot
+pub trait AccountFetcher {
+    /// Returns the account at `pubkey` or `None` if it does not exist
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, FlashSdkError>;
+}
+
+impl AccountFetcher for RpcClient {
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, FlashSdkError> {
+        Ok(self
+            .get_account_with_commitment(pubkey, self.commitment())
+            .map_err(|_| FlashSdkError::RpcError)?
+            .value)
+    }
+}
+
+impl AccountFetcher for HashMap<Pubkey, Account> {
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, FlashSdkError> {
+        Ok(self.get(pubkey).cloned())
+    }
+}
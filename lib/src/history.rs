@@ -0,0 +1,531 @@
+//! Flash loans reconstructed from confirmed transactions
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use solana_program::pubkey::Pubkey;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, UiInstruction, UiTransactionTokenBalance,
+};
+
+use crate::error::FlashSdkError;
+use crate::instruction::FlashLoanInstruction;
+
+/// How the liquidity was lent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlashLoanKind {
+    /// `FlashBorrow` and `FlashRepay` instruction pair
+    BorrowRepay,
+    /// `FlashLoan` instruction calling the receiver program
+    FlashLoan,
+}
+
+/// Flash loan found in a confirmed transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlashLoanRecord {
+    /// How the liquidity was lent
+    pub kind: FlashLoanKind,
+    /// Index of the top level instruction which is or invoked `FlashBorrow` / `FlashLoan`
+    pub instruction_index: u8,
+    /// Reserve the liquidity was borrowed from
+    pub reserve: Pubkey,
+    /// Token account receiving the borrowed liquidity
+    pub liquidity_account: Pubkey,
+    /// `FlashRepay` transfer authority or, for `FlashLoan`, the owner of the liquidity account
+    /// when the transaction metadata has it
+    pub borrower: Option<Pubkey>,
+    /// Borrowed amount
+    pub amount: u64,
+    /// Fee actually paid, from token balance changes of the reserve liquidity supply and fee
+    /// receiver. `None` when the balances are missing or the transaction contains several flash
+    /// loans from the same reserve.
+    pub fee: Option<u64>,
+}
+
+/// Flash Loan program instruction with resolved accounts
+struct FlashInstruction {
+    top_level_index: u8,
+    instruction: FlashLoanInstruction,
+    accounts: Vec<Pubkey>,
+}
+
+/// Returns flash loans of `program_id` executed by `transaction`, including ones invoked through
+/// CPI. Failed transactions contain no flash loans.
+///
+/// Only binary transaction encodings are supported: the transaction has to be fetched with
+/// `UiTransactionEncoding::Base64` or `Base58`.
+pub fn extract_flash_loans(
+    program_id: &Pubkey,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<Vec<FlashLoanRecord>, FlashSdkError> {
+    let meta = transaction
+        .transaction
+        .meta
+        .as_ref()
+        .ok_or(FlashSdkError::DeserializationError)?;
+    if meta.err.is_some() {
+        return Ok(vec![]);
+    }
+    let versioned_transaction = transaction
+        .transaction
+        .transaction
+        .decode()
+        .ok_or(FlashSdkError::DeserializationError)?;
+    let message = &versioned_transaction.message;
+
+    let mut account_keys = message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded_addresses) = &meta.loaded_addresses {
+        for address in loaded_addresses
+            .writable
+            .iter()
+            .chain(loaded_addresses.readonly.iter())
+        {
+            account_keys.push(parse_pubkey(address)?);
+        }
+    }
+    let resolve = |indices: &[u8]| -> Result<Vec<Pubkey>, FlashSdkError> {
+        indices
+            .iter()
+            .map(|index| {
+                account_keys
+                    .get(*index as usize)
+                    .copied()
+                    .ok_or(FlashSdkError::DeserializationError)
+            })
+            .collect()
+    };
+
+    let inner_instructions = match &meta.inner_instructions {
+        OptionSerializer::Some(inner_instructions) => inner_instructions.as_slice(),
+        _ => &[],
+    };
+
+    // Flash Loan instructions in execution order
+    let mut flash_instructions = vec![];
+    for (index, instruction) in message.instructions().iter().enumerate() {
+        let index = index as u8;
+        let mut instructions = vec![(
+            instruction.program_id_index,
+            instruction.accounts.clone(),
+            instruction.data.clone(),
+        )];
+        for inner in inner_instructions
+            .iter()
+            .filter(|inner| inner.index == index)
+        {
+            for instruction in &inner.instructions {
+                match instruction {
+                    UiInstruction::Compiled(instruction) => instructions.push((
+                        instruction.program_id_index,
+                        instruction.accounts.clone(),
+                        bs58::decode(&instruction.data)
+                            .into_vec()
+                            .map_err(|_| FlashSdkError::DeserializationError)?,
+                    )),
+                    UiInstruction::Parsed(_) => return Err(FlashSdkError::DeserializationError),
+                }
+            }
+        }
+
+        for (program_id_index, accounts, data) in instructions {
+            if account_keys.get(program_id_index as usize) != Some(program_id) {
+                continue;
+            }
+            if let Ok(instruction) = FlashLoanInstruction::unpack(&data) {
+                flash_instructions.push(FlashInstruction {
+                    top_level_index: index,
+                    instruction,
+                    accounts: resolve(&accounts)?,
+                });
+            }
+        }
+    }
+
+    let mut records = vec![];
+    // Reserve liquidity supply and fee receiver of each record
+    let mut fee_accounts = vec![];
+    for (position, flash_instruction) in flash_instructions.iter().enumerate() {
+        let accounts = &flash_instruction.accounts;
+        match flash_instruction.instruction {
+            FlashLoanInstruction::FlashBorrow { amount } if accounts.len() >= 3 => {
+                let reserve = accounts[2];
+                let repay = flash_instructions[position + 1..].iter().find(|other| {
+                    other.accounts.len() >= 6
+                        && other.accounts[3] == reserve
+                        && other.instruction == FlashLoanInstruction::FlashRepay { amount }
+                });
+                if let Some(repay) = repay {
+                    records.push(FlashLoanRecord {
+                        kind: FlashLoanKind::BorrowRepay,
+                        instruction_index: flash_instruction.top_level_index,
+                        reserve,
+                        liquidity_account: accounts[1],
+                        borrower: Some(repay.accounts[5]),
+                        amount,
+                        fee: None,
+                    });
+                    fee_accounts.push((accounts[0], repay.accounts[2]));
+                }
+            }
+            FlashLoanInstruction::FlashLoan { amount, .. } if accounts.len() >= 4 => {
+                records.push(FlashLoanRecord {
+                    kind: FlashLoanKind::FlashLoan,
+                    instruction_index: flash_instruction.top_level_index,
+                    reserve: accounts[2],
+                    liquidity_account: accounts[1],
+                    borrower: token_owner(&account_keys, &meta.post_token_balances, &accounts[1]),
+                    amount,
+                    fee: None,
+                });
+                fee_accounts.push((accounts[0], accounts[3]));
+            }
+            _ => {}
+        }
+    }
+
+    let mut loans_per_reserve = HashMap::new();
+    for record in &records {
+        *loans_per_reserve.entry(record.reserve).or_insert(0) += 1;
+    }
+    for (record, (supply, fee_receiver)) in records.iter_mut().zip(fee_accounts) {
+        if loans_per_reserve[&record.reserve] != 1 {
+            continue;
+        }
+        let delta = |account: &Pubkey| -> Option<i128> {
+            let post = token_amount(&account_keys, &meta.post_token_balances, account)?;
+            let pre = token_amount(&account_keys, &meta.pre_token_balances, account).unwrap_or(0);
+            Some(post as i128 - pre as i128)
+        };
+        record.fee = match (delta(&supply), delta(&fee_receiver)) {
+            (Some(supply), Some(fee_receiver)) if supply + fee_receiver >= 0 => {
+                Some((supply + fee_receiver) as u64)
+            }
+            _ => None,
+        };
+    }
+
+    Ok(records)
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey, FlashSdkError> {
+    Pubkey::from_str(pubkey).map_err(|_| FlashSdkError::DeserializationError)
+}
+
+fn token_balance<'a>(
+    account_keys: &[Pubkey],
+    balances: &'a OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    account: &Pubkey,
+) -> Option<&'a UiTransactionTokenBalance> {
+    match balances {
+        OptionSerializer::Some(balances) => balances
+            .iter()
+            .find(|balance| account_keys.get(balance.account_index as usize) == Some(account)),
+        _ => None,
+    }
+}
+
+fn token_amount(
+    account_keys: &[Pubkey],
+    balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    account: &Pubkey,
+) -> Option<u64> {
+    token_balance(account_keys, balances, account)?
+        .ui_token_amount
+        .amount
+        .parse()
+        .ok()
+}
+
+fn token_owner(
+    account_keys: &[Pubkey],
+    balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    account: &Pubkey,
+) -> Option<Pubkey> {
+    match &token_balance(account_keys, balances, account)?.owner {
+        OptionSerializer::Some(owner) => parse_pubkey(owner).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use solana_account_decoder::parse_token::token_amount_to_ui_amount;
+    use solana_program::instruction::{AccountMeta, CompiledInstruction, Instruction};
+    use solana_program::message::Message;
+    use solana_sdk::transaction::{Transaction, TransactionError};
+    use solana_transaction_status::{
+        ConfirmedTransactionWithStatusMeta, InnerInstructions, TransactionStatusMeta,
+        TransactionTokenBalance, TransactionWithStatusMeta, UiTransactionEncoding,
+        VersionedTransactionWithStatusMeta,
+    };
+
+    use super::*;
+    use crate::instruction::{flash_borrow, flash_loan, flash_repay};
+    use crate::FLASH_LOAN_ID;
+
+    struct Accounts {
+        supply: Pubkey,
+        user_liquidity: Pubkey,
+        reserve: Pubkey,
+        fee_receiver: Pubkey,
+        lending_market: Pubkey,
+        user: Pubkey,
+    }
+
+    impl Accounts {
+        fn new() -> Self {
+            Self {
+                supply: Pubkey::new_unique(),
+                user_liquidity: Pubkey::new_unique(),
+                reserve: Pubkey::new_unique(),
+                fee_receiver: Pubkey::new_unique(),
+                lending_market: Pubkey::new_unique(),
+                user: Pubkey::new_unique(),
+            }
+        }
+
+        fn token_balances(
+            &self,
+            message: &Message,
+            balances: &[(Pubkey, u64)],
+        ) -> Vec<TransactionTokenBalance> {
+            balances
+                .iter()
+                .map(|(account, amount)| TransactionTokenBalance {
+                    account_index: message
+                        .account_keys
+                        .iter()
+                        .position(|key| key == account)
+                        .unwrap() as u8,
+                    mint: Pubkey::new_unique().to_string(),
+                    ui_token_amount: token_amount_to_ui_amount(*amount, 9),
+                    owner: self.user.to_string(),
+                    program_id: spl_token::id().to_string(),
+                })
+                .collect()
+        }
+    }
+
+    fn encode(
+        message: Message,
+        meta: TransactionStatusMeta,
+    ) -> EncodedConfirmedTransactionWithStatusMeta {
+        ConfirmedTransactionWithStatusMeta {
+            slot: 42,
+            tx_with_meta: TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+                transaction: Transaction::new_unsigned(message).into(),
+                meta,
+            }),
+            block_time: None,
+        }
+        .encode(UiTransactionEncoding::Base64, Some(0))
+        .unwrap()
+    }
+
+    fn borrow_repay_message(accounts: &Accounts, amount: u64) -> Message {
+        let other = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        Message::new(
+            &[
+                flash_borrow(
+                    FLASH_LOAN_ID,
+                    amount,
+                    accounts.supply,
+                    accounts.user_liquidity,
+                    accounts.reserve,
+                    accounts.lending_market,
+                ),
+                other,
+                flash_repay(
+                    FLASH_LOAN_ID,
+                    amount,
+                    accounts.user_liquidity,
+                    accounts.supply,
+                    accounts.fee_receiver,
+                    accounts.reserve,
+                    accounts.lending_market,
+                    accounts.user,
+                ),
+            ],
+            Some(&accounts.user),
+        )
+    }
+
+    #[test]
+    fn extract_borrow_repay() {
+        let accounts = Accounts::new();
+        let message = borrow_repay_message(&accounts, 1_000_000_000);
+        let meta = TransactionStatusMeta {
+            pre_token_balances: Some(accounts.token_balances(
+                &message,
+                &[
+                    (accounts.supply, 1_000_000_000_000),
+                    (accounts.fee_receiver, 0),
+                ],
+            )),
+            post_token_balances: Some(accounts.token_balances(
+                &message,
+                &[
+                    (accounts.supply, 1_000_002_400_000),
+                    (accounts.fee_receiver, 600_000),
+                ],
+            )),
+            ..TransactionStatusMeta::default()
+        };
+
+        assert_eq!(
+            extract_flash_loans(&FLASH_LOAN_ID, &encode(message, meta)),
+            Ok(vec![FlashLoanRecord {
+                kind: FlashLoanKind::BorrowRepay,
+                instruction_index: 0,
+                reserve: accounts.reserve,
+                liquidity_account: accounts.user_liquidity,
+                borrower: Some(accounts.user),
+                amount: 1_000_000_000,
+                fee: Some(3_000_000),
+            }])
+        );
+    }
+
+    #[test]
+    fn extract_flash_loan_cpi() {
+        let accounts = Accounts::new();
+        let flash_loan = flash_loan(
+            FLASH_LOAN_ID,
+            1_000_000_000,
+            0,
+            accounts.supply,
+            accounts.user_liquidity,
+            accounts.reserve,
+            accounts.fee_receiver,
+            accounts.lending_market,
+            Pubkey::new_unique(),
+            vec![],
+        );
+        // Top level instruction of an arbitrage program invoking FlashLoan
+        let mut arbitrage_accounts = flash_loan.accounts.clone();
+        arbitrage_accounts.push(AccountMeta::new_readonly(FLASH_LOAN_ID, false));
+        let message = Message::new(
+            &[Instruction::new_with_bytes(
+                Pubkey::new_unique(),
+                &[],
+                arbitrage_accounts,
+            )],
+            Some(&accounts.user),
+        );
+        let index_of = |pubkey: &Pubkey| {
+            message
+                .account_keys
+                .iter()
+                .position(|key| key == pubkey)
+                .unwrap() as u8
+        };
+        let inner = CompiledInstruction {
+            program_id_index: index_of(&FLASH_LOAN_ID),
+            accounts: flash_loan
+                .accounts
+                .iter()
+                .map(|meta| index_of(&meta.pubkey))
+                .collect(),
+            data: flash_loan.data.clone(),
+        };
+        let meta = TransactionStatusMeta {
+            inner_instructions: Some(vec![InnerInstructions {
+                index: 0,
+                instructions: vec![inner],
+            }]),
+            pre_token_balances: Some(accounts.token_balances(
+                &message,
+                &[
+                    (accounts.supply, 1_000_000_000_000),
+                    (accounts.user_liquidity, 5_000_000),
+                ],
+            )),
+            post_token_balances: Some(accounts.token_balances(
+                &message,
+                &[
+                    (accounts.supply, 1_000_002_400_000),
+                    (accounts.fee_receiver, 600_000),
+                    (accounts.user_liquidity, 2_000_000),
+                ],
+            )),
+            ..TransactionStatusMeta::default()
+        };
+
+        assert_eq!(
+            extract_flash_loans(&FLASH_LOAN_ID, &encode(message, meta)),
+            Ok(vec![FlashLoanRecord {
+                kind: FlashLoanKind::FlashLoan,
+                instruction_index: 0,
+                reserve: accounts.reserve,
+                liquidity_account: accounts.user_liquidity,
+                borrower: Some(accounts.user),
+                amount: 1_000_000_000,
+                fee: Some(3_000_000),
+            }])
+        );
+    }
+
+    #[test]
+    fn skip_failed_and_unpaired() {
+        let accounts = Accounts::new();
+        let failed = TransactionStatusMeta {
+            status: Err(TransactionError::AccountNotFound),
+            ..TransactionStatusMeta::default()
+        };
+        assert_eq!(
+            extract_flash_loans(
+                &FLASH_LOAN_ID,
+                &encode(borrow_repay_message(&accounts, 1_000), failed)
+            ),
+            Ok(vec![])
+        );
+
+        let message = borrow_repay_message(&accounts, 1_000);
+        assert_eq!(
+            extract_flash_loans(
+                &Pubkey::new_unique(),
+                &encode(message.clone(), TransactionStatusMeta::default())
+            ),
+            Ok(vec![])
+        );
+
+        let records = extract_flash_loans(
+            &FLASH_LOAN_ID,
+            &encode(message, TransactionStatusMeta::default()),
+        )
+        .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].fee, None);
+
+        // FlashBorrow without a FlashRepay, alone or with a repay to another reserve
+        let borrow = flash_borrow(
+            FLASH_LOAN_ID,
+            1_000,
+            accounts.supply,
+            accounts.user_liquidity,
+            accounts.reserve,
+            accounts.lending_market,
+        );
+        let other_reserve_repay = flash_repay(
+            FLASH_LOAN_ID,
+            1_000,
+            accounts.user_liquidity,
+            accounts.supply,
+            accounts.fee_receiver,
+            Pubkey::new_unique(),
+            accounts.lending_market,
+            accounts.user,
+        );
+        for instructions in [vec![borrow.clone()], vec![borrow, other_reserve_repay]].iter() {
+            let unpaired = Message::new(instructions, Some(&accounts.user));
+            assert_eq!(
+                extract_flash_loans(
+                    &FLASH_LOAN_ID,
+                    &encode(unpaired, TransactionStatusMeta::default())
+                ),
+                Ok(vec![])
+            );
+        }
+    }
+}
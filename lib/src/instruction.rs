@@ -1,15 +1,22 @@
 //! Instruction types
 
+use std::convert::TryInto;
 use std::mem::size_of;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use solana_program::{
     instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
     pubkey::{Pubkey, PUBKEY_BYTES},
     sysvar,
 };
 
+use crate::error::FlashProgramError;
+
 /// Instructions supported by the Flash Loan program.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum FlashLoanInstruction {
     // 5
     /// Make a CPI style flash loan.
@@ -66,6 +73,7 @@ pub enum FlashLoanInstruction {
     ///   4. `[]` Derived lending market authority.
     ///   5. `[]` Instructions sysvar.
     ///   6. `[]` Token program.
+    ///   7. `[]` Reserve liquidity mint. Optional, enables `TransferChecked` for transfer fee mints.
     FlashBorrow {
         /// Amount of liquidity to flash borrow
         amount: u64,
@@ -86,6 +94,7 @@ pub enum FlashLoanInstruction {
     ///   5. `[signer]` User transfer authority ($authority).
     ///   6. `[]` Instructions sysvar.
     ///   7. `[]` Token program id.
+    ///   8. `[]` Reserve liquidity mint. Optional, enables `TransferChecked` for transfer fee mints.
     FlashRepay {
         /// Amount of liquidity to flash repay. Must be the same as in paired FlashBorrow IX.
         amount: u64,
@@ -93,6 +102,46 @@ pub enum FlashLoanInstruction {
 }
 
 impl FlashLoanInstruction {
+    /// Unpacks a byte buffer into a [FlashLoanInstruction](enum.FlashLoanInstruction.html).
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&tag, rest) = input
+            .split_first()
+            .ok_or(FlashProgramError::InstructionUnpackError)?;
+        Ok(match tag {
+            5 => {
+                let (amount, rest) = Self::unpack_u64(rest)?;
+                let (&receive_flash_loan_instruction_tag, _rest) = rest
+                    .split_first()
+                    .ok_or(FlashProgramError::InstructionUnpackError)?;
+                Self::FlashLoan {
+                    amount,
+                    receive_flash_loan_instruction_tag,
+                }
+            }
+            7 => {
+                let (amount, _rest) = Self::unpack_u64(rest)?;
+                Self::FlashBorrow { amount }
+            }
+            8 => {
+                let (amount, _rest) = Self::unpack_u64(rest)?;
+                Self::FlashRepay { amount }
+            }
+            _ => return Err(FlashProgramError::InstructionUnpackError.into()),
+        })
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        if input.len() < 8 {
+            return Err(FlashProgramError::InstructionUnpackError.into());
+        }
+        let (amount, rest) = input.split_at(8);
+        let amount = amount
+            .try_into()
+            .map(u64::from_le_bytes)
+            .map_err(|_| FlashProgramError::InstructionUnpackError)?;
+        Ok((amount, rest))
+    }
+
     /// Packs a [FlashLoanInstruction](enum.FlashLoanInstruction.html) into a byte buffer.
     pub fn pack(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(size_of::<Self>());
@@ -118,7 +167,7 @@ impl FlashLoanInstruction {
     }
 }
 
-/// Creates a `FlashLoan` instruction.
+/// Creates a `FlashLoan` instruction for a reserve whose liquidity mint is owned by SPL Token.
 #[allow(clippy::too_many_arguments)]
 pub fn flash_loan(
     program_id: Pubkey,
@@ -132,10 +181,39 @@ pub fn flash_loan(
     flash_loan_receiver_program_id: Pubkey,
     flash_loan_receiver_program_accounts: Vec<AccountMeta>,
 ) -> Instruction {
-    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
-        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
-        &program_id,
-    );
+    flash_loan_with_token_program(
+        program_id,
+        amount,
+        receive_flash_loan_instruction_tag,
+        source_liquidity_pubkey,
+        destination_liquidity_pubkey,
+        reserve_pubkey,
+        reserve_liquidity_fee_receiver_pubkey,
+        lending_market_pubkey,
+        flash_loan_receiver_program_id,
+        flash_loan_receiver_program_accounts,
+        spl_token::id(),
+    )
+}
+
+/// Creates a `FlashLoan` instruction.
+/// `token_program_id` is the program owning the reserve liquidity mint: SPL Token or Token-2022.
+#[allow(clippy::too_many_arguments)]
+pub fn flash_loan_with_token_program(
+    program_id: Pubkey,
+    amount: u64,
+    receive_flash_loan_instruction_tag: u8,
+    source_liquidity_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    flash_loan_receiver_program_id: Pubkey,
+    flash_loan_receiver_program_accounts: Vec<AccountMeta>,
+    token_program_id: Pubkey,
+) -> Instruction {
+    let lending_market_authority_pubkey =
+        find_lending_market_authority(&program_id, &lending_market_pubkey);
     let mut accounts = vec![
         AccountMeta::new(source_liquidity_pubkey, false),
         AccountMeta::new(destination_liquidity_pubkey, false),
@@ -143,7 +221,7 @@ pub fn flash_loan(
         AccountMeta::new(reserve_liquidity_fee_receiver_pubkey, false),
         AccountMeta::new_readonly(lending_market_pubkey, false),
         AccountMeta::new_readonly(lending_market_authority_pubkey, false),
-        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(token_program_id, false),
         AccountMeta::new_readonly(flash_loan_receiver_program_id, false),
     ];
     accounts.extend(flash_loan_receiver_program_accounts);
@@ -158,7 +236,19 @@ pub fn flash_loan(
     }
 }
 
-/// Creates a 'FlashBorrow' instruction.
+/// Derives the lending market authority of `lending_market_pubkey`.
+pub fn find_lending_market_authority(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        program_id,
+    )
+    .0
+}
+
+/// Creates a 'FlashBorrow' instruction for a reserve whose liquidity mint is owned by SPL Token.
 pub fn flash_borrow(
     program_id: Pubkey,
     amount: u64,
@@ -167,27 +257,53 @@ pub fn flash_borrow(
     reserve_pubkey: Pubkey,
     lending_market_pubkey: Pubkey,
 ) -> Instruction {
-    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
-        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
-        &program_id,
-    );
+    flash_borrow_with_token_program(
+        program_id,
+        amount,
+        source_liquidity_pubkey,
+        destination_liquidity_pubkey,
+        reserve_pubkey,
+        lending_market_pubkey,
+        spl_token::id(),
+        None,
+    )
+}
 
+/// Creates a 'FlashBorrow' instruction.
+/// `token_program_id` is the program owning the reserve liquidity mint: SPL Token or Token-2022.
+/// `liquidity_mint_pubkey` is passed to transfer with `TransferChecked`, which Token-2022 mints with
+/// a transfer fee require.
+#[allow(clippy::too_many_arguments)]
+pub fn flash_borrow_with_token_program(
+    program_id: Pubkey,
+    amount: u64,
+    source_liquidity_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    token_program_id: Pubkey,
+    liquidity_mint_pubkey: Option<Pubkey>,
+) -> Instruction {
+    let lending_market_authority_pubkey =
+        find_lending_market_authority(&program_id, &lending_market_pubkey);
+    let mut accounts = vec![
+        AccountMeta::new(source_liquidity_pubkey, false),
+        AccountMeta::new(destination_liquidity_pubkey, false),
+        AccountMeta::new(reserve_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        AccountMeta::new_readonly(token_program_id, false),
+    ];
+    accounts.extend(liquidity_mint_pubkey.map(|mint| AccountMeta::new_readonly(mint, false)));
     Instruction {
         program_id,
-        accounts: vec![
-            AccountMeta::new(source_liquidity_pubkey, false),
-            AccountMeta::new(destination_liquidity_pubkey, false),
-            AccountMeta::new(reserve_pubkey, false),
-            AccountMeta::new_readonly(lending_market_pubkey, false),
-            AccountMeta::new_readonly(lending_market_authority_pubkey, false),
-            AccountMeta::new_readonly(sysvar::instructions::id(), false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-        ],
+        accounts,
         data: FlashLoanInstruction::FlashBorrow { amount }.pack(),
     }
 }
 
-/// Creates a 'FlashRepay' instruction.
+/// Creates a 'FlashRepay' instruction for a reserve whose liquidity mint is owned by SPL Token.
 #[allow(clippy::too_many_arguments)]
 pub fn flash_repay(
     program_id: Pubkey,
@@ -199,18 +315,92 @@ pub fn flash_repay(
     lending_market_pubkey: Pubkey,
     user_transfer_authority_pubkey: Pubkey,
 ) -> Instruction {
+    flash_repay_with_token_program(
+        program_id,
+        amount,
+        source_liquidity_pubkey,
+        destination_liquidity_pubkey,
+        reserve_liquidity_fee_receiver_pubkey,
+        reserve_pubkey,
+        lending_market_pubkey,
+        user_transfer_authority_pubkey,
+        spl_token::id(),
+        None,
+    )
+}
+
+/// Creates a 'FlashRepay' instruction.
+/// `token_program_id` is the program owning the reserve liquidity mint: SPL Token or Token-2022.
+/// `liquidity_mint_pubkey` is passed to transfer with `TransferChecked`, which Token-2022 mints with
+/// a transfer fee require.
+#[allow(clippy::too_many_arguments)]
+pub fn flash_repay_with_token_program(
+    program_id: Pubkey,
+    amount: u64,
+    source_liquidity_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+    token_program_id: Pubkey,
+    liquidity_mint_pubkey: Option<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(source_liquidity_pubkey, false),
+        AccountMeta::new(destination_liquidity_pubkey, false),
+        AccountMeta::new(reserve_liquidity_fee_receiver_pubkey, false),
+        AccountMeta::new(reserve_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        AccountMeta::new_readonly(token_program_id, false),
+    ];
+    accounts.extend(liquidity_mint_pubkey.map(|mint| AccountMeta::new_readonly(mint, false)));
     Instruction {
         program_id,
-        accounts: vec![
-            AccountMeta::new(source_liquidity_pubkey, false),
-            AccountMeta::new(destination_liquidity_pubkey, false),
-            AccountMeta::new(reserve_liquidity_fee_receiver_pubkey, false),
-            AccountMeta::new(reserve_pubkey, false),
-            AccountMeta::new_readonly(lending_market_pubkey, false),
-            AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
-            AccountMeta::new_readonly(sysvar::instructions::id(), false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-        ],
+        accounts,
         data: FlashLoanInstruction::FlashRepay { amount }.pack(),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pack_unpack() {
+        let instructions = [
+            FlashLoanInstruction::FlashLoan {
+                amount: 1_000_000,
+                receive_flash_loan_instruction_tag: 3,
+            },
+            FlashLoanInstruction::FlashBorrow { amount: u64::MAX },
+            FlashLoanInstruction::FlashRepay { amount: 42 },
+        ];
+
+        for instruction in instructions.iter() {
+            assert_eq!(
+                FlashLoanInstruction::unpack(&instruction.pack()).unwrap(),
+                *instruction
+            );
+        }
+    }
+
+    #[test]
+    fn unpack_invalid() {
+        let err: ProgramError = FlashProgramError::InstructionUnpackError.into();
+        assert_eq!(FlashLoanInstruction::unpack(&[]), Err(err.clone()));
+        assert_eq!(FlashLoanInstruction::unpack(&[1, 0, 0]), Err(err.clone()));
+        assert_eq!(
+            FlashLoanInstruction::unpack(&[7, 1, 2, 3]),
+            Err(err.clone())
+        );
+        assert_eq!(
+            FlashLoanInstruction::unpack(
+                &FlashLoanInstruction::FlashBorrow { amount: 1 }.pack()[..8]
+            ),
+            Err(err)
+        );
+    }
+}
@@ -1,17 +1,39 @@
+use solana_account_decoder::UiAccountEncoding;
 use solana_client::rpc_client::RpcClient;
-use solana_program::program_pack::Pack;
-use solana_program::pubkey::Pubkey;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_program::program_pack::{IsInitialized, Pack};
+use solana_program::{pubkey, pubkey::Pubkey};
 
 use crate::error::{FlashProgramError, FlashSdkError};
-use crate::math::{Decimal, Rate, TryMul};
-use crate::types::Reserve;
+use crate::math::{Decimal, Rate, RoundingMode, TryMul};
+use crate::types::{Reserve, RESERVE_VERSION};
 
+pub mod amount;
+pub mod builder;
+pub mod cache;
+pub mod cluster;
 pub mod error;
+pub mod fetcher;
+pub mod history;
 pub mod instruction;
+pub mod logs;
 pub mod math;
+pub mod profit;
+pub mod quote;
+pub mod sender;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod simulation;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod token;
+pub mod transaction;
 pub mod types;
+pub mod watcher;
 
-pub const FLASH_LOAN_ID: &str = "F1aShdFVv12jar3oM2fi6SDqbefSnnCVRzaxbPH3you7";
+/// Flash Loan program id on devnet and mainnet
+pub const FLASH_LOAN_ID: Pubkey = pubkey!("F1aShdFVv12jar3oM2fi6SDqbefSnnCVRzaxbPH3you7");
 
 /// Calculates total fees for flash borrow of specified `amount`
 /// Type of token to be borrowed is determined by `reserve`
@@ -21,15 +43,36 @@ pub fn flash_loan_fee_via_rpc(
     borrow_amount: u64,
     rpc_client: &RpcClient,
 ) -> Result<u64, FlashSdkError> {
-    let reserve = get_reserve(reserve_key, rpc_client)?;
+    flash_loan_fee_via_rpc_with_program_id(&FLASH_LOAN_ID, reserve_key, borrow_amount, rpc_client)
+}
+
+/// Same as [flash_loan_fee_via_rpc](fn.flash_loan_fee_via_rpc.html) for a reserve of
+/// `program_id`, e.g. of a [custom cluster](cluster/enum.Cluster.html) or a local deployment.
+pub fn flash_loan_fee_via_rpc_with_program_id(
+    program_id: &Pubkey,
+    reserve_key: &Pubkey,
+    borrow_amount: u64,
+    rpc_client: &RpcClient,
+) -> Result<u64, FlashSdkError> {
+    let reserve = get_reserve_with_program_id(program_id, reserve_key, rpc_client)?;
 
     flash_loan_fee(&reserve, borrow_amount)
 }
 
+/// Rounding of the flash loan fee, the same as the Flash Loan program uses
+pub const FLASH_LOAN_FEE_ROUNDING: RoundingMode = RoundingMode::HalfUp;
+
 /// Calculates total fees for flash borrow of specified `amount`
 /// Type of token to be borrowed is determined by `reserve`
+/// The fee is rounded half up, see [FLASH_LOAN_FEE_ROUNDING](constant.FLASH_LOAN_FEE_ROUNDING.html).
+/// A `flash_loan_fee_wad` of `u64::MAX` disables flash loans for the reserve.
 pub fn flash_loan_fee(reserve: &Reserve, borrow_amount: u64) -> Result<u64, FlashSdkError> {
-    let borrow_fee_rate = Rate::from_scaled_val(reserve.config.fees.flash_loan_fee_wad);
+    if reserve.config.fees.flash_loan_fee_wad == u64::MAX {
+        return Err(FlashSdkError::FlashError(
+            FlashProgramError::FlashLoansDisabled,
+        ));
+    }
+    let borrow_fee_rate = Rate::from_scaled_val(reserve.config.fees.flash_loan_fee_wad as u128);
     let texture_fee_rate = Rate::from_percent(reserve.config.fees.texture_fee_percentage);
     let borrow_amount = Decimal::from(borrow_amount);
 
@@ -50,7 +93,7 @@ pub fn flash_loan_fee(reserve: &Reserve, borrow_amount: u64) -> Result<u64, Flas
         }
 
         let borrow_fee = borrow_fee_decimal
-            .try_round_u64()
+            .try_to_u64(FLASH_LOAN_FEE_ROUNDING)
             .map_err(|_| FlashSdkError::FlashError(FlashProgramError::MathOverflow))?;
 
         Ok(borrow_fee)
@@ -65,19 +108,109 @@ pub fn available_liquidity_via_rpc(
     reserve_key: &Pubkey,
     rpc_client: &RpcClient,
 ) -> Result<u64, FlashSdkError> {
-    let reserve = get_reserve(reserve_key, rpc_client)?;
+    available_liquidity_via_rpc_with_program_id(&FLASH_LOAN_ID, reserve_key, rpc_client)
+}
+
+/// Same as [available_liquidity_via_rpc](fn.available_liquidity_via_rpc.html) for a reserve of
+/// `program_id`.
+pub fn available_liquidity_via_rpc_with_program_id(
+    program_id: &Pubkey,
+    reserve_key: &Pubkey,
+    rpc_client: &RpcClient,
+) -> Result<u64, FlashSdkError> {
+    let reserve = get_reserve_with_program_id(program_id, reserve_key, rpc_client)?;
 
     Ok(available_liquidity(&reserve))
 }
 
 /// Returns deserialized Reserve structure getting it from account specified by `reserve_key`
-/// via provided RpcClient
+/// via provided RpcClient.
+/// Fails with `ReserveNotFound` if the account does not exist and if it is not an initialized
+/// Reserve of the Flash Loan program [FLASH_LOAN_ID](constant.FLASH_LOAN_ID.html).
 pub fn get_reserve(reserve_key: &Pubkey, rpc_client: &RpcClient) -> Result<Reserve, FlashSdkError> {
-    let raw_data = rpc_client
-        .get_account_data(reserve_key)
+    get_reserve_with_program_id(&FLASH_LOAN_ID, reserve_key, rpc_client)
+}
+
+/// Same as [get_reserve](fn.get_reserve.html) for a reserve of `program_id`, e.g. of a
+/// [custom cluster](cluster/enum.Cluster.html) or a local deployment.
+pub fn get_reserve_with_program_id(
+    program_id: &Pubkey,
+    reserve_key: &Pubkey,
+    rpc_client: &RpcClient,
+) -> Result<Reserve, FlashSdkError> {
+    let account = rpc_client
+        .get_account_with_commitment(reserve_key, rpc_client.commitment())
+        .map_err(|_| FlashSdkError::RpcError)?
+        .value
+        .ok_or(FlashSdkError::ReserveNotFound(*reserve_key))?;
+
+    unpack_reserve(program_id, &account.owner, &account.data)
+}
+
+/// Offset of `liquidity.mint_pubkey` in Reserve account data
+pub const RESERVE_LIQUIDITY_MINT_OFFSET: usize = 48;
+
+/// Returns all reserves of `program_id` via provided RpcClient, only the ones lending `mint` if
+/// given. Accounts which are not valid reserves are skipped.
+pub fn get_reserves(
+    program_id: &Pubkey,
+    mint: Option<&Pubkey>,
+    rpc_client: &RpcClient,
+) -> Result<Vec<(Pubkey, Reserve)>, FlashSdkError> {
+    let mut filters = vec![RpcFilterType::DataSize(Reserve::LEN as u64)];
+    if let Some(mint) = mint {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            RESERVE_LIQUIDITY_MINT_OFFSET,
+            mint.as_ref(),
+        )));
+    }
+    let accounts = rpc_client
+        .get_program_accounts_with_config(
+            program_id,
+            RpcProgramAccountsConfig {
+                filters: Some(filters),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )
         .map_err(|_| FlashSdkError::RpcError)?;
 
-    let reserve = Reserve::unpack(&*raw_data).map_err(|_| FlashSdkError::DeserializationError)?;
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(pubkey, account)| {
+            unpack_reserve(program_id, &account.owner, &account.data)
+                .ok()
+                .map(|reserve| (pubkey, reserve))
+        })
+        .collect())
+}
+
+/// Deserializes Reserve from raw account `data` checking that the account is owned by
+/// `program_id`, is initialized and has a supported layout version.
+pub fn unpack_reserve(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+) -> Result<Reserve, FlashSdkError> {
+    if owner != program_id {
+        return Err(FlashSdkError::InvalidReserveOwner);
+    }
+
+    let reserve =
+        Reserve::unpack_unchecked(data).map_err(|_| FlashSdkError::DeserializationError)?;
+    if !reserve.is_initialized() {
+        return Err(FlashSdkError::ReserveNotInitialized);
+    }
+    if reserve.version != RESERVE_VERSION {
+        return Err(FlashSdkError::UnsupportedReserveVersion(reserve.version));
+    }
+    // Mint decimals are a u8
+    if reserve.liquidity.mint_decimals > u8::MAX as u64 {
+        return Err(FlashSdkError::DeserializationError);
+    }
 
     Ok(reserve)
 }
@@ -87,3 +220,329 @@ pub fn get_reserve(reserve_key: &Pubkey, rpc_client: &RpcClient) -> Result<Reser
 pub fn available_liquidity(reserve: &Reserve) -> u64 {
     reserve.liquidity.available_amount
 }
+
+#[cfg(test)]
+mod test {
+    use num_traits::FromPrimitive;
+    use proptest::prelude::*;
+    use solana_program::instruction::InstructionError;
+    use solana_sdk::transaction::TransactionError;
+
+    use super::*;
+    use crate::math::WAD;
+    use crate::testing::{FlashLoanFixture, FlashLoanFixtureConfig, ReserveBuilder};
+
+    fn fee_reserve(flash_loan_fee_wad: u64, texture_fee_percentage: u8) -> Reserve {
+        let mut reserve = ReserveBuilder::new()
+            .texture_fee_percentage(texture_fee_percentage)
+            .build();
+        reserve.config.fees.flash_loan_fee_wad = flash_loan_fee_wad;
+        reserve
+    }
+
+    /// fee_wad, texture_fee_percentage, amount, expected fee and the source line
+    type FeeVector = (u64, u8, u64, Result<u64, FlashSdkError>, &'static str);
+
+    /// Golden vectors of `fixtures/fees/flash_loan_fee.txt`
+    fn fee_vectors() -> Vec<FeeVector> {
+        include_str!("../fixtures/fees/flash_loan_fee.txt")
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let values = line.split(" # ").next().unwrap();
+                let values = values.split_whitespace().collect::<Vec<_>>();
+                let expected = match values[3] {
+                    "BorrowTooSmall" => {
+                        Err(FlashSdkError::FlashError(FlashProgramError::BorrowTooSmall))
+                    }
+                    "FlashLoansDisabled" => Err(FlashSdkError::FlashError(
+                        FlashProgramError::FlashLoansDisabled,
+                    )),
+                    fee => Ok(fee.parse().unwrap()),
+                };
+                (
+                    values[0].parse().unwrap(),
+                    values[1].parse().unwrap(),
+                    values[2].parse().unwrap(),
+                    expected,
+                    line,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flash_loan_fee_golden_vectors() {
+        for (fee_wad, texture_fee_percentage, amount, expected, line) in fee_vectors() {
+            let reserve = fee_reserve(fee_wad, texture_fee_percentage);
+            assert_eq!(flash_loan_fee(&reserve, amount), expected, "{}", line);
+        }
+    }
+
+    /// Charges every golden vector with the Flash Loan program binary at `FLASH_LOAN_PROGRAM_SO`.
+    /// The program may need a real lending market, read from `FLASH_LOAN_LENDING_MARKET_DATA`.
+    /// Run with `cargo test --features testing fee_vectors_match_program -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn fee_vectors_match_program() {
+        let program_so =
+            std::env::var_os("FLASH_LOAN_PROGRAM_SO").expect("FLASH_LOAN_PROGRAM_SO is not set");
+        let lending_market_data = std::env::var_os("FLASH_LOAN_LENDING_MARKET_DATA")
+            .map(|path| std::fs::read(path).expect("Reading lending market data"))
+            .unwrap_or_default();
+
+        for (fee_wad, texture_fee_percentage, amount, expected, line) in fee_vectors() {
+            // The program rejects zero amounts, which the SDK quotes as free
+            if amount == 0 {
+                continue;
+            }
+            // The user holds at most as much as borrowed, without overflowing its balance
+            let user_liquidity = amount.min(u64::MAX - amount);
+            if matches!(expected, Ok(fee) if fee > user_liquidity) {
+                continue;
+            }
+            let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig {
+                program_so: Some(program_so.clone().into()),
+                lending_market_data: lending_market_data.clone(),
+                reserve_liquidity: amount,
+                user_liquidity,
+                ..FlashLoanFixtureConfig::default()
+            })
+            .await;
+            let mut reserve = fixture.reserve().await;
+            reserve.config.fees.flash_loan_fee_wad = fee_wad;
+            reserve.config.fees.texture_fee_percentage = texture_fee_percentage;
+            let mut account = fixture
+                .context
+                .banks_client
+                .get_account(fixture.reserve_pubkey)
+                .await
+                .unwrap()
+                .unwrap();
+            Reserve::pack(reserve, &mut account.data).unwrap();
+            fixture
+                .context
+                .set_account(&fixture.reserve_pubkey, &account.into());
+
+            let instructions = [fixture.flash_borrow(amount), fixture.flash_repay(amount)];
+            let charged = match fixture.process_transaction(&instructions).await {
+                Ok(()) => Ok(user_liquidity - fixture.token_balance(fixture.user_liquidity).await),
+                Err(err) => match err.unwrap() {
+                    TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+                        Err(FlashSdkError::FlashError(
+                            FlashProgramError::from_u32(code).expect("Flash Loan program error"),
+                        ))
+                    }
+                    err => panic!("{}: {}", line, err),
+                },
+            };
+            assert_eq!(charged, expected, "{}", line);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn flash_loan_fee_bounds(
+            flash_loan_fee_wad in prop_oneof![Just(0), 1..WAD, any::<u64>()],
+            texture_fee_percentage in prop_oneof![Just(0), 1..=100u8],
+            amount in prop_oneof![0..10_000u64, any::<u64>(), u64::MAX - 10_000..=u64::MAX],
+        ) {
+            let reserve = fee_reserve(flash_loan_fee_wad, texture_fee_percentage);
+            let fee = flash_loan_fee(&reserve, amount);
+
+            // Never overflows, the unrounded fee is below `amount` which fits into u64
+            prop_assert_ne!(
+                fee.clone(),
+                Err(FlashSdkError::FlashError(FlashProgramError::MathOverflow))
+            );
+            if flash_loan_fee_wad == u64::MAX {
+                prop_assert_eq!(
+                    fee,
+                    Err(FlashSdkError::FlashError(FlashProgramError::FlashLoansDisabled))
+                );
+                return Ok(());
+            }
+            if let Ok(fee) = fee {
+                prop_assert!(fee <= amount);
+                let minimum_fee = if texture_fee_percentage > 0 { 2 } else { 1 };
+                if flash_loan_fee_wad > 0 && amount > 0 {
+                    prop_assert!(fee >= minimum_fee);
+                } else {
+                    prop_assert_eq!(fee, 0);
+                }
+            }
+        }
+
+        #[test]
+        fn flash_loan_fee_is_monotonic(
+            flash_loan_fee_wad in 0..WAD / 10,
+            texture_fee_percentage in 0..=100u8,
+            amount in 0..u64::MAX,
+        ) {
+            let reserve = fee_reserve(flash_loan_fee_wad, texture_fee_percentage);
+            if let (Ok(fee), Ok(next_fee)) = (
+                flash_loan_fee(&reserve, amount),
+                flash_loan_fee(&reserve, amount + 1),
+            ) {
+                prop_assert!(fee <= next_fee);
+            }
+        }
+    }
+
+    #[test]
+    fn unpack_valid_reserve() {
+        let builder = ReserveBuilder::new();
+        let account = builder.account();
+
+        assert_eq!(
+            unpack_reserve(&account.owner, &account.owner, &account.data),
+            Ok(builder.build())
+        );
+    }
+
+    #[test]
+    fn get_reserve_of_custom_program() {
+        let builder = ReserveBuilder::new().available_liquidity(100);
+        let program_id = Pubkey::new_unique();
+        let mut account = builder.account();
+        account.owner = program_id;
+        let reserve_key = Pubkey::new_unique();
+        let rpc_client = || {
+            let mut mocks = std::collections::HashMap::new();
+            mocks.insert(
+                solana_client::rpc_request::RpcRequest::GetAccountInfo,
+                serde_json::json!({
+                    "context": {"slot": 1},
+                    "value": solana_account_decoder::UiAccount::encode(
+                        &reserve_key,
+                        &account,
+                        UiAccountEncoding::Base64,
+                        None,
+                        None,
+                    ),
+                }),
+            );
+            RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks)
+        };
+
+        assert_eq!(
+            get_reserve(&reserve_key, &rpc_client()),
+            Err(FlashSdkError::InvalidReserveOwner)
+        );
+        assert_eq!(
+            get_reserve_with_program_id(&program_id, &reserve_key, &rpc_client()),
+            Ok(builder.build())
+        );
+        assert_eq!(
+            available_liquidity_via_rpc_with_program_id(&program_id, &reserve_key, &rpc_client()),
+            Ok(100)
+        );
+    }
+
+    #[test]
+    fn get_missing_reserve() {
+        let reserve_key = Pubkey::new_unique();
+        let mut mocks = std::collections::HashMap::new();
+        mocks.insert(
+            solana_client::rpc_request::RpcRequest::GetAccountInfo,
+            serde_json::json!({"context": {"slot": 1}, "value": null}),
+        );
+        let rpc_client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        assert_eq!(
+            get_reserve(&reserve_key, &rpc_client),
+            Err(FlashSdkError::ReserveNotFound(reserve_key))
+        );
+        assert_eq!(
+            get_reserve(&reserve_key, &RpcClient::new_mock("fails".to_string())),
+            Err(FlashSdkError::RpcError)
+        );
+    }
+
+    #[test]
+    fn unpack_reserve_checks() {
+        let account = ReserveBuilder::new().account();
+        let program_id = account.owner;
+
+        assert_eq!(
+            unpack_reserve(&program_id, &spl_token::id(), &account.data),
+            Err(FlashSdkError::InvalidReserveOwner)
+        );
+        assert_eq!(
+            unpack_reserve(&program_id, &program_id, &account.data[1..]),
+            Err(FlashSdkError::DeserializationError)
+        );
+
+        let mut reserve = Reserve::unpack(&account.data).unwrap();
+        let mut data = account.data.clone();
+        reserve.version = 0;
+        reserve.pack_into_slice(&mut data);
+        assert_eq!(
+            unpack_reserve(&program_id, &program_id, &data),
+            Err(FlashSdkError::ReserveNotInitialized)
+        );
+
+        reserve.version = RESERVE_VERSION + 1;
+        reserve.pack_into_slice(&mut data);
+        assert_eq!(
+            unpack_reserve(&program_id, &program_id, &data),
+            Err(FlashSdkError::UnsupportedReserveVersion(
+                RESERVE_VERSION + 1
+            ))
+        );
+
+        reserve.version = RESERVE_VERSION;
+        reserve.liquidity.mint_decimals = u8::MAX as u64 + 1;
+        reserve.pack_into_slice(&mut data);
+        assert_eq!(
+            unpack_reserve(&program_id, &program_id, &data),
+            Err(FlashSdkError::DeserializationError)
+        );
+        assert_eq!(reserve.liquidity.decimals(), u8::MAX);
+    }
+
+    #[test]
+    fn flash_loan_fee_rounding() {
+        // 0.3% fee without Texture share, so the minimum fee is 1
+        let reserve = ReserveBuilder::new()
+            .fee_bps(30)
+            .texture_fee_percentage(0)
+            .build();
+
+        // 1.5 is rounded up where floor gives 1, 3.498 and 2.001 down where ceil gives 4 and 3
+        assert_eq!(flash_loan_fee(&reserve, 500), Ok(2));
+        assert_eq!(flash_loan_fee(&reserve, 1166), Ok(3));
+        assert_eq!(flash_loan_fee(&reserve, 667), Ok(2));
+
+        // Exact .5 fractions go up, anything below them down
+        for (amount, expected_fee) in [
+            (499u64, 1),
+            (1_500, 5),
+            (2_500, 8),
+            (2_499, 7),
+            (1_000_000_001, 3_000_000),
+            (1_000_000_166, 3_000_000),
+            (1_000_000_167, 3_000_001),
+            (1_000_000_000_500, 3_000_000_002),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                flash_loan_fee(&reserve, *amount),
+                Ok(*expected_fee),
+                "{}",
+                amount
+            );
+        }
+    }
+
+    #[test]
+    fn reserve_liquidity_mint_offset() {
+        let mint = Pubkey::new_unique();
+        let data = ReserveBuilder::new().mint(mint).pack();
+
+        assert_eq!(
+            &data[RESERVE_LIQUIDITY_MINT_OFFSET..RESERVE_LIQUIDITY_MINT_OFFSET + 32],
+            mint.as_ref()
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! Flash Loan program log parser
+//!
+//! Turns `meta.log_messages` of a transaction into [FlashLoanEvent](enum.FlashLoanEvent.html)s.
+//! Only `Program log:` lines emitted while the Flash Loan program is executing are considered:
+//!
+//! * `Flash borrow <amount> from reserve <reserve>`
+//! * `Flash repay <amount> with fee <fee>`
+//! * `Flash loan <amount> from reserve <reserve>, fee <fee>`
+//!
+//! These are the messages of the Rust [mock program](../testing/mock_program/index.html), which
+//! `fixtures/logs/mock` is made of. They are checked against the deployed program by recording its
+//! transactions into `fixtures/logs/devnet` with the ignored `record_devnet_transactions` test:
+//! every recorded flash loan must be found in the logs by this parser.
+//!
+//! A custom error is reported only when it originates in the Flash Loan program. Errors of
+//! programs it invokes, e.g. SPL Token, are propagated by the runtime with the same code and are
+//! not attributed to it.
+
+use std::str::FromStr;
+
+use num_traits::FromPrimitive;
+use solana_program::pubkey::Pubkey;
+
+use crate::error::FlashProgramError;
+
+/// Event logged by the Flash Loan program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlashLoanEvent {
+    /// `FlashBorrow` lent `amount` from `reserve`
+    FlashBorrowed { reserve: Pubkey, amount: u64 },
+    /// `FlashRepay` returned `amount` and paid `fee`
+    FlashRepaid { amount: u64, fee: u64 },
+    /// `FlashLoan` lent `amount` from `reserve` to the receiver program and got it back with `fee`
+    FlashLoaned {
+        reserve: Pubkey,
+        amount: u64,
+        fee: u64,
+    },
+    /// Flash Loan program instruction failed with custom error `code`, decoded into `error` when
+    /// known
+    Failed {
+        code: u32,
+        error: Option<FlashProgramError>,
+    },
+}
+
+/// Parses events logged by `program_id` from transaction `logs`
+pub fn parse_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<FlashLoanEvent> {
+    let program_id = program_id.to_string();
+    let mut events = vec![];
+    let mut invocations: Vec<&str> = vec![];
+    let mut failed = false;
+
+    for log in logs {
+        let log = log.as_ref();
+        if let Some(message) = log.strip_prefix("Program log: ") {
+            if invocations.last() == Some(&program_id.as_str()) {
+                events.extend(parse_message(message));
+            }
+        } else if let Some(rest) = log.strip_prefix("Program ") {
+            let mut parts = rest.splitn(2, ' ');
+            let (id, status) = match (parts.next(), parts.next()) {
+                (Some(id), Some(status)) => (id, status),
+                _ => continue,
+            };
+            if status.starts_with("invoke [") {
+                invocations.push(id);
+            } else if status == "success" {
+                invocations.pop();
+            } else if let Some(reason) = status.strip_prefix("failed: ") {
+                invocations.pop();
+                // Callers of a failed program fail with the same error
+                if !failed && id == program_id {
+                    if let Some(code) = reason
+                        .strip_prefix("custom program error: 0x")
+                        .and_then(|code| u32::from_str_radix(code, 16).ok())
+                    {
+                        events.push(FlashLoanEvent::Failed {
+                            code,
+                            error: FlashProgramError::from_u32(code),
+                        });
+                    }
+                }
+                failed = true;
+            }
+        }
+    }
+
+    events
+}
+
+fn parse_message(message: &str) -> Option<FlashLoanEvent> {
+    if let Some(rest) = message.strip_prefix("Flash borrow ") {
+        let (amount, reserve) = rest.split_once(" from reserve ")?;
+        Some(FlashLoanEvent::FlashBorrowed {
+            reserve: Pubkey::from_str(reserve).ok()?,
+            amount: amount.parse().ok()?,
+        })
+    } else if let Some(rest) = message.strip_prefix("Flash repay ") {
+        let (amount, fee) = rest.split_once(" with fee ")?;
+        Some(FlashLoanEvent::FlashRepaid {
+            amount: amount.parse().ok()?,
+            fee: fee.parse().ok()?,
+        })
+    } else if let Some(rest) = message.strip_prefix("Flash loan ") {
+        let (amount, rest) = rest.split_once(" from reserve ")?;
+        let (reserve, fee) = rest.split_once(", fee ")?;
+        Some(FlashLoanEvent::FlashLoaned {
+            reserve: Pubkey::from_str(reserve).ok()?,
+            amount: amount.parse().ok()?,
+            fee: fee.parse().ok()?,
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use solana_client::rpc_client::RpcClient;
+    use solana_client::rpc_config::RpcTransactionConfig;
+    use solana_program_test::BanksClientError;
+    use solana_sdk::commitment_config::CommitmentConfig;
+    use solana_sdk::signature::Signature;
+    use solana_transaction_status::option_serializer::OptionSerializer;
+    use solana_transaction_status::{
+        EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding,
+    };
+
+    use super::*;
+    use crate::history::{extract_flash_loans, FlashLoanKind};
+    use crate::testing::{FlashLoanFixture, FlashLoanFixtureConfig};
+    use crate::FLASH_LOAN_ID;
+
+    /// Confirmed transactions of the deployed program, see `record_devnet_transactions`
+    fn devnet_transactions_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/logs/devnet")
+    }
+
+    // Mock fixtures follow the runtime log format with the messages of the mock program
+    fn fixture(log: &str) -> Vec<&str> {
+        log.lines().collect()
+    }
+
+    fn reserve() -> Pubkey {
+        Pubkey::from_str("9Wys2sCHcAGZm3jgSnfP8xyq1ZiK2qthQ4Ki5fSdkqP").unwrap()
+    }
+
+    #[test]
+    fn parse_flash_borrow_repay() {
+        let logs = fixture(include_str!("../fixtures/logs/mock/flash_borrow_repay.log"));
+
+        assert_eq!(
+            parse_logs(&FLASH_LOAN_ID, &logs),
+            vec![
+                FlashLoanEvent::FlashBorrowed {
+                    reserve: reserve(),
+                    amount: 1_000_000_000
+                },
+                FlashLoanEvent::FlashRepaid {
+                    amount: 1_000_000_000,
+                    fee: 3_000_000
+                },
+            ]
+        );
+        assert!(parse_logs(&Pubkey::new_unique(), &logs).is_empty());
+    }
+
+    #[test]
+    fn parse_flash_loan_cpi() {
+        let logs = fixture(include_str!("../fixtures/logs/mock/flash_loan_cpi.log"));
+
+        assert_eq!(
+            parse_logs(&FLASH_LOAN_ID, &logs),
+            vec![FlashLoanEvent::FlashLoaned {
+                reserve: reserve(),
+                amount: 1_000_000_000,
+                fee: 3_000_000
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let logs = fixture(include_str!(
+            "../fixtures/logs/mock/flash_borrow_without_repay.log"
+        ));
+        assert_eq!(
+            parse_logs(&FLASH_LOAN_ID, &logs),
+            vec![FlashLoanEvent::Failed {
+                code: 28,
+                error: Some(FlashProgramError::NoFlashRepayFound)
+            }]
+        );
+
+        let logs = fixture(include_str!(
+            "../fixtures/logs/mock/flash_loan_receiver_failed.log"
+        ));
+        assert!(parse_logs(&FLASH_LOAN_ID, &logs).is_empty());
+    }
+
+    #[tokio::test]
+    async fn parse_simulation_logs() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let amount = 1_000_000_000;
+
+        let logs = fixture
+            .simulate_transaction(&[fixture.flash_borrow(amount), fixture.flash_repay(amount)])
+            .await
+            .unwrap();
+        assert_eq!(
+            parse_logs(&fixture.program_id, &logs),
+            vec![
+                FlashLoanEvent::FlashBorrowed {
+                    reserve: fixture.reserve_pubkey,
+                    amount
+                },
+                FlashLoanEvent::FlashRepaid {
+                    amount,
+                    fee: 3_000_000
+                },
+            ]
+        );
+
+        match fixture
+            .simulate_transaction(&[fixture.flash_borrow(amount)])
+            .await
+        {
+            Err(BanksClientError::SimulationError { logs, .. }) => assert_eq!(
+                parse_logs(&fixture.program_id, &logs),
+                vec![FlashLoanEvent::Failed {
+                    code: 28,
+                    error: Some(FlashProgramError::NoFlashRepayFound)
+                }]
+            ),
+            result => panic!("unexpected simulation result {:?}", result),
+        }
+    }
+
+    /// Records recent successful transactions of the Flash Loan program from `FLASH_LOAN_RPC_URL`
+    /// (devnet by default) for `parse_recorded_transactions`.
+    /// Run with `cargo test --features testing record_devnet_transactions -- --ignored`.
+    #[test]
+    #[ignore]
+    fn record_devnet_transactions() {
+        let url = std::env::var("FLASH_LOAN_RPC_URL")
+            .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
+        let rpc_client = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
+        let dir = devnet_transactions_dir();
+        fs::create_dir_all(&dir).unwrap();
+
+        let signatures = rpc_client
+            .get_signatures_for_address(&FLASH_LOAN_ID)
+            .unwrap();
+        for status in signatures
+            .iter()
+            .filter(|status| status.err.is_none())
+            .take(20)
+        {
+            let signature = Signature::from_str(&status.signature).unwrap();
+            let transaction = rpc_client
+                .get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Base64),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .unwrap();
+            if extract_flash_loans(&FLASH_LOAN_ID, &transaction)
+                .unwrap()
+                .is_empty()
+            {
+                continue;
+            }
+            fs::write(
+                dir.join(format!("{}.json", signature)),
+                serde_json::to_string_pretty(&transaction).unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    /// Every flash loan found in a recorded transaction by its instructions and token balances has
+    /// to be found in its logs too. Needs the transactions of `record_devnet_transactions`.
+    /// Run with `cargo test parse_recorded_transactions -- --ignored`.
+    #[test]
+    #[ignore]
+    fn parse_recorded_transactions() {
+        let dir = devnet_transactions_dir();
+        let paths = fs::read_dir(&dir)
+            .unwrap_or_else(|err| panic!("No recorded transactions in {}: {}", dir.display(), err))
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert!(
+            !paths.is_empty(),
+            "No recorded transactions in {}",
+            dir.display()
+        );
+        for path in paths {
+            let transaction: EncodedConfirmedTransactionWithStatusMeta =
+                serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let logs = match &transaction.transaction.meta.as_ref().unwrap().log_messages {
+                OptionSerializer::Some(logs) => logs.clone(),
+                _ => panic!("{} has no logs", path.display()),
+            };
+            let events = parse_logs(&FLASH_LOAN_ID, &logs);
+
+            for record in extract_flash_loans(&FLASH_LOAN_ID, &transaction).unwrap() {
+                let found = match record.kind {
+                    FlashLoanKind::BorrowRepay => {
+                        events.contains(&FlashLoanEvent::FlashBorrowed {
+                            reserve: record.reserve,
+                            amount: record.amount,
+                        }) && events.iter().any(|event| match event {
+                            FlashLoanEvent::FlashRepaid { amount, fee } => {
+                                *amount == record.amount && record.fee.unwrap_or(*fee) == *fee
+                            }
+                            _ => false,
+                        })
+                    }
+                    FlashLoanKind::FlashLoan => events.iter().any(|event| match event {
+                        FlashLoanEvent::FlashLoaned {
+                            reserve,
+                            amount,
+                            fee,
+                        } => {
+                            *reserve == record.reserve
+                                && *amount == record.amount
+                                && record.fee.unwrap_or(*fee) == *fee
+                        }
+                        _ => false,
+                    }),
+                };
+                assert!(
+                    found,
+                    "{:?} not found in the logs of {}: {:?}",
+                    record,
+                    path.display(),
+                    events
+                );
+            }
+        }
+    }
+}
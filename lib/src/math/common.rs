@@ -1,6 +1,7 @@
 //! Common module for Decimal and Rate
 
 use solana_program::program_error::ProgramError;
+use thiserror::Error;
 
 /// Scale of precision
 pub const SCALE: usize = 18;
@@ -10,17 +11,19 @@ pub const WAD: u64 = 1_000_000_000_000_000_000;
 pub const HALF_WAD: u64 = 500_000_000_000_000_000;
 /// Scale for percentages
 pub const PERCENT_SCALER: u64 = 10_000_000_000_000_000;
+/// Scale for basis points
+pub const BPS_SCALER: u64 = 100_000_000_000_000;
 
 /// Try to subtract, return an error on underflow
-pub trait TrySub: Sized {
+pub trait TrySub<RHS = Self>: Sized {
     /// Subtract
-    fn try_sub(self, rhs: Self) -> Result<Self, ProgramError>;
+    fn try_sub(self, rhs: RHS) -> Result<Self, ProgramError>;
 }
 
 /// Try to subtract, return an error on overflow
-pub trait TryAdd: Sized {
+pub trait TryAdd<RHS = Self>: Sized {
     /// Add
-    fn try_add(self, rhs: Self) -> Result<Self, ProgramError>;
+    fn try_add(self, rhs: RHS) -> Result<Self, ProgramError>;
 }
 
 /// Try to divide, return an error on overflow or divide by zero
@@ -34,3 +37,42 @@ pub trait TryMul<RHS>: Sized {
     /// Multiply
     fn try_mul(self, rhs: RHS) -> Result<Self, ProgramError>;
 }
+
+/// Error of parsing a Decimal or Rate from a string
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+pub enum ParseDecimalError {
+    /// Not a number of decimal digits with an optional fractional part
+    #[error("Invalid decimal number")]
+    Invalid,
+    /// More significant fractional digits than the precision of 18 digits allows
+    #[error("Too many fractional digits")]
+    TooManyFractionDigits,
+    /// Value does not fit into the underlying integer
+    #[error("Decimal number overflow")]
+    Overflow,
+}
+
+/// Digits of a decimal number string scaled by 10^`scale`, e.g. "1.5" with scale 18 gives
+/// "1500000000000000000"
+pub(crate) fn scaled_digits(s: &str, scale: usize) -> Result<String, ParseDecimalError> {
+    let (integer, fraction) = match s.split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (s, ""),
+    };
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if integer.is_empty() && fraction.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+        return Err(ParseDecimalError::Invalid);
+    }
+    // Trailing zeros do not change the value
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > scale {
+        return Err(ParseDecimalError::TooManyFractionDigits);
+    }
+
+    Ok(format!(
+        "{}{}{}",
+        integer,
+        fraction,
+        "0".repeat(scale - fraction.len())
+    ))
+}
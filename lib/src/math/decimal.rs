@@ -12,7 +12,7 @@
 #![allow(clippy::ptr_offset_with_cast)]
 #![allow(clippy::manual_range_contains)]
 
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, fmt, str::FromStr};
 
 use solana_program::program_error::ProgramError;
 use uint::construct_uint;
@@ -27,6 +27,17 @@ construct_uint! {
     pub struct U192(3);
 }
 
+/// How a Decimal is rounded to an integer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Towards zero, e.g. 1.9 -> 1
+    Floor,
+    /// Away from zero, e.g. 1.1 -> 2
+    Ceil,
+    /// To the nearest integer with halves rounded up, e.g. 1.5 -> 2 and 1.49 -> 1
+    HalfUp,
+}
+
 /// Large decimal values, precise to 18 digits
 #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Decimal(pub U192);
@@ -57,6 +68,29 @@ impl Decimal {
         Self(U192::from(percent as u64 * PERCENT_SCALER))
     }
 
+    /// Create scaled decimal from basis points, 30 bps = 0.3% = 0.003
+    pub fn from_bps(bps: u16) -> Self {
+        Self(U192::from(bps as u64 * BPS_SCALER))
+    }
+
+    /// Return value in basis points. Fails with `InvalidAmount` if it is not a whole number of
+    /// them and with `MathOverflow` if it exceeds u16.
+    pub fn to_bps(&self) -> Result<u16, ProgramError> {
+        let bps_scaler = U192::from(BPS_SCALER);
+        if !(self.0 % bps_scaler).is_zero() {
+            return Err(FlashError::InvalidAmount.into());
+        }
+        Ok(u16::try_from(self.0 / bps_scaler).map_err(|_| FlashError::MathOverflow)?)
+    }
+
+    /// Parse a percent value exactly, "0.3" = 0.3% = 0.003
+    pub fn from_percent_decimal(percent: &str) -> Result<Self, ParseDecimalError> {
+        let scaled_val = scaled_digits(percent, SCALE - 2)?;
+        Ok(Self(
+            U192::from_dec_str(&scaled_val).map_err(|_| ParseDecimalError::Overflow)?,
+        ))
+    }
+
     /// Return raw scaled value if it fits within u128
     #[allow(clippy::wrong_self_convention)]
     pub fn to_scaled_val(&self) -> Result<u128, ProgramError> {
@@ -68,6 +102,15 @@ impl Decimal {
         Self(U192::from(scaled_val))
     }
 
+    /// Convert scaled decimal to u64 rounding with `mode`
+    pub fn try_to_u64(&self, mode: RoundingMode) -> Result<u64, ProgramError> {
+        match mode {
+            RoundingMode::Floor => self.try_floor_u64(),
+            RoundingMode::Ceil => self.try_ceil_u64(),
+            RoundingMode::HalfUp => self.try_round_u64(),
+        }
+    }
+
     /// Round scaled decimal to u64
     pub fn try_round_u64(&self) -> Result<u64, ProgramError> {
         let rounded_val = Self::half_wad()
@@ -98,6 +141,44 @@ impl Decimal {
             .ok_or(FlashError::MathOverflow)?;
         Ok(u64::try_from(ceil_val).map_err(|_| FlashError::MathOverflow)?)
     }
+
+    /// Largest value, use `Ord::min` and `Ord::max` to compare
+    pub fn max_value() -> Self {
+        Self(U192::MAX)
+    }
+
+    /// Calculates base^exp
+    pub fn try_pow(&self, mut exp: u64) -> Result<Decimal, ProgramError> {
+        let mut base = *self;
+        let mut ret = Self::one();
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                ret = ret.try_mul(base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.try_mul(base)?;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Add, return the largest value on overflow
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+
+    /// Subtract, return zero on underflow
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Multiply, return the largest value on overflow
+    pub fn saturating_mul(self, rhs: Self) -> Self {
+        self.try_mul(rhs).unwrap_or_else(|_| Self::max_value())
+    }
 }
 
 impl fmt::Display for Decimal {
@@ -113,6 +194,18 @@ impl fmt::Display for Decimal {
     }
 }
 
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    /// Parses a decimal number, e.g. "0.003", exactly
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let scaled_val = scaled_digits(s, SCALE)?;
+        Ok(Self(
+            U192::from_dec_str(&scaled_val).map_err(|_| ParseDecimalError::Overflow)?,
+        ))
+    }
+}
+
 impl From<u64> for Decimal {
     fn from(val: u64) -> Self {
         Self(Self::wad() * U192::from(val))
@@ -147,6 +240,30 @@ impl TrySub for Decimal {
     }
 }
 
+impl TryAdd<u64> for Decimal {
+    fn try_add(self, rhs: u64) -> Result<Self, ProgramError> {
+        self.try_add(Self::from(rhs))
+    }
+}
+
+impl TryAdd<Rate> for Decimal {
+    fn try_add(self, rhs: Rate) -> Result<Self, ProgramError> {
+        self.try_add(Self::from(rhs))
+    }
+}
+
+impl TrySub<u64> for Decimal {
+    fn try_sub(self, rhs: u64) -> Result<Self, ProgramError> {
+        self.try_sub(Self::from(rhs))
+    }
+}
+
+impl TrySub<Rate> for Decimal {
+    fn try_sub(self, rhs: Rate) -> Result<Self, ProgramError> {
+        self.try_sub(Self::from(rhs))
+    }
+}
+
 impl TryDiv<u64> for Decimal {
     fn try_div(self, rhs: u64) -> Result<Self, ProgramError> {
         Ok(Self(
@@ -157,6 +274,16 @@ impl TryDiv<u64> for Decimal {
     }
 }
 
+impl TryDiv<u128> for Decimal {
+    fn try_div(self, rhs: u128) -> Result<Self, ProgramError> {
+        Ok(Self(
+            self.0
+                .checked_div(U192::from(rhs))
+                .ok_or(FlashError::MathOverflow)?,
+        ))
+    }
+}
+
 impl TryDiv<Rate> for Decimal {
     fn try_div(self, rhs: Rate) -> Result<Self, ProgramError> {
         self.try_div(Self::from(rhs))
@@ -185,6 +312,16 @@ impl TryMul<u64> for Decimal {
     }
 }
 
+impl TryMul<u128> for Decimal {
+    fn try_mul(self, rhs: u128) -> Result<Self, ProgramError> {
+        Ok(Self(
+            self.0
+                .checked_mul(U192::from(rhs))
+                .ok_or(FlashError::MathOverflow)?,
+        ))
+    }
+}
+
 impl TryMul<Rate> for Decimal {
     fn try_mul(self, rhs: Rate) -> Result<Self, ProgramError> {
         self.try_mul(Self::from(rhs))
@@ -205,10 +342,203 @@ impl TryMul<Decimal> for Decimal {
 
 #[cfg(test)]
 mod test {
+    use proptest::prelude::*;
+
     use super::*;
 
     #[test]
     fn test_scaler() {
         assert_eq!(U192::exp10(SCALE), Decimal::wad());
     }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn overflow<T>() -> Result<T, ProgramError> {
+        Err(FlashError::MathOverflow.into())
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Decimal::from(2u64), d("2"));
+        assert_eq!(Decimal::from(2u128), d("2"));
+        assert_eq!(Decimal::from(u128::MAX).to_scaled_val(), overflow());
+        assert_eq!(Decimal::from(Rate::from_percent(30)), d("0.3"));
+        assert_eq!(Decimal::from_percent(30), d("0.3"));
+        assert_eq!(Decimal::from_scaled_val(1), d("0.000000000000000001"));
+        assert_eq!(d("1.5").to_scaled_val(), Ok(1_500_000_000_000_000_000));
+    }
+
+    #[test]
+    fn bps_and_percent() {
+        assert_eq!(Decimal::from_bps(30), d("0.003"));
+        assert_eq!(Decimal::from_bps(30), Decimal::from(Rate::from_bps(30)));
+        assert_eq!(d("0.003").to_bps(), Ok(30));
+        assert_eq!(d("0.00025").to_bps(), Err(FlashError::InvalidAmount.into()));
+        assert_eq!(d("7").to_bps(), overflow());
+
+        assert_eq!(Decimal::from_percent_decimal("0.3"), Ok(d("0.003")));
+        assert_eq!(Decimal::from_percent_decimal("250"), Ok(d("2.5")));
+        assert_eq!(
+            Decimal::from_percent_decimal("0.00000000000000001"),
+            Err(ParseDecimalError::TooManyFractionDigits)
+        );
+    }
+
+    #[test]
+    fn add_and_sub() {
+        assert_eq!(d("1.5").try_add(d("0.25")), Ok(d("1.75")));
+        assert_eq!(d("1.5").try_add(2u64), Ok(d("3.5")));
+        assert_eq!(d("1.5").try_add(Rate::from_percent(50)), Ok(d("2")));
+        assert_eq!(
+            Decimal::max_value().try_add(d("0.000000000000000001")),
+            overflow()
+        );
+        assert_eq!(Decimal::max_value().try_add(1u64), overflow());
+
+        assert_eq!(d("1.5").try_sub(d("0.25")), Ok(d("1.25")));
+        assert_eq!(d("1.5").try_sub(1u64), Ok(d("0.5")));
+        assert_eq!(d("1.5").try_sub(Rate::from_percent(50)), Ok(d("1")));
+        assert_eq!(d("1.5").try_sub(d("1.6")), overflow());
+        assert_eq!(d("1.5").try_sub(2u64), overflow());
+        assert_eq!(d("0.1").try_sub(Rate::one()), overflow());
+    }
+
+    #[test]
+    fn mul_and_div() {
+        assert_eq!(d("1.5").try_mul(d("0.5")), Ok(d("0.75")));
+        assert_eq!(d("1.5").try_mul(3u64), Ok(d("4.5")));
+        assert_eq!(d("1.5").try_mul(3u128), Ok(d("4.5")));
+        assert_eq!(d("1.5").try_mul(Rate::from_percent(10)), Ok(d("0.15")));
+        // Precision is limited to 18 digits
+        assert_eq!(
+            d("0.000000001").try_mul(d("0.0000000001")),
+            Ok(Decimal::zero())
+        );
+        assert_eq!(Decimal::max_value().try_mul(d("1")), overflow());
+        assert_eq!(Decimal::max_value().try_mul(2u64), overflow());
+        assert_eq!(Decimal::max_value().try_mul(2u128), overflow());
+        assert_eq!(Decimal::max_value().try_mul(Rate::one()), overflow());
+
+        assert_eq!(d("1.5").try_div(d("0.5")), Ok(d("3")));
+        assert_eq!(d("1.5").try_div(3u64), Ok(d("0.5")));
+        assert_eq!(d("1.5").try_div(3u128), Ok(d("0.5")));
+        assert_eq!(d("1.5").try_div(Rate::from_percent(50)), Ok(d("3")));
+        assert_eq!(d("1").try_div(3u64), Ok(d("0.333333333333333333")));
+        assert_eq!(d("1").try_div(Decimal::zero()), overflow());
+        assert_eq!(d("1").try_div(0u64), overflow());
+        assert_eq!(d("1").try_div(0u128), overflow());
+        assert_eq!(d("1").try_div(Rate::zero()), overflow());
+        assert_eq!(Decimal::max_value().try_div(d("1")), overflow());
+    }
+
+    #[test]
+    fn pow() {
+        assert_eq!(d("1.5").try_pow(0), Ok(Decimal::one()));
+        assert_eq!(d("1.5").try_pow(1), Ok(d("1.5")));
+        assert_eq!(d("1.5").try_pow(2), Ok(d("2.25")));
+        assert_eq!(d("2").try_pow(63), Ok(Decimal::from(1u64 << 63)));
+        assert_eq!(d("0.5").try_pow(3), Ok(d("0.125")));
+        assert_eq!(Decimal::one().try_pow(u64::MAX), Ok(Decimal::one()));
+        assert_eq!(Decimal::zero().try_pow(u64::MAX), Ok(Decimal::zero()));
+        // Does not square the base beyond the highest bit of the exponent
+        assert_eq!(
+            Decimal::from(u64::MAX).try_pow(1),
+            Ok(Decimal::from(u64::MAX))
+        );
+        assert_eq!(d("2").try_pow(130), overflow());
+    }
+
+    #[test]
+    fn saturating_and_min_max() {
+        let max = Decimal::max_value();
+        assert_eq!(d("1").saturating_add(d("2")), d("3"));
+        assert_eq!(max.saturating_add(d("1")), max);
+        assert_eq!(d("2").saturating_sub(d("1")), d("1"));
+        assert_eq!(d("1").saturating_sub(d("2")), Decimal::zero());
+        assert_eq!(d("2").saturating_mul(d("1.5")), d("3"));
+        assert_eq!(max.saturating_mul(d("2")), max);
+
+        assert_eq!(d("1").min(d("2")), d("1"));
+        assert_eq!(d("1").max(d("2")), d("2"));
+        assert_eq!(Decimal::zero().max(max), max);
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let cases = [
+            ("0", 0, 0, 0),
+            ("1", 1, 1, 1),
+            ("1.000000000000000001", 1, 2, 1),
+            ("1.499999999999999999", 1, 2, 1),
+            ("1.5", 1, 2, 2),
+            ("1.999999999999999999", 1, 2, 2),
+            ("18446744073709551615", u64::MAX, u64::MAX, u64::MAX),
+        ];
+        for (decimal, floor, ceil, half_up) in cases.iter() {
+            let decimal: Decimal = decimal.parse().unwrap();
+            assert_eq!(decimal.try_to_u64(RoundingMode::Floor), Ok(*floor));
+            assert_eq!(decimal.try_to_u64(RoundingMode::Ceil), Ok(*ceil));
+            assert_eq!(decimal.try_to_u64(RoundingMode::HalfUp), Ok(*half_up));
+        }
+
+        let decimal: Decimal = "18446744073709551615.5".parse().unwrap();
+        assert_eq!(decimal.try_to_u64(RoundingMode::Floor), Ok(u64::MAX));
+        for mode in [RoundingMode::Ceil, RoundingMode::HalfUp].iter() {
+            assert_eq!(
+                decimal.try_to_u64(*mode),
+                Err(FlashError::MathOverflow.into())
+            );
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("1".parse(), Ok(Decimal::one()));
+        assert_eq!("0".parse(), Ok(Decimal::zero()));
+        assert_eq!(
+            "0.5".parse(),
+            Ok(Decimal::from_scaled_val(HALF_WAD as u128))
+        );
+        assert_eq!(".5".parse(), Ok(Decimal::from_scaled_val(HALF_WAD as u128)));
+        assert_eq!("42.".parse(), Ok(Decimal::from(42u64)));
+        assert_eq!(
+            "0.000000000000000001".parse(),
+            Ok(Decimal::from_scaled_val(1))
+        );
+        assert_eq!(
+            "0.1000000000000000000000".parse(),
+            Ok(Decimal::from_scaled_val(WAD as u128 / 10))
+        );
+        assert_eq!("18446744073709551615".parse(), Ok(Decimal::from(u64::MAX)));
+        let max = Decimal(U192::MAX);
+        assert_eq!(max.to_string().parse(), Ok(max));
+
+        for invalid in ["", ".", "-1", "+1", "1.2.3", "1e18", " 1", "0x10", "1_000"].iter() {
+            assert_eq!(
+                invalid.parse::<Decimal>(),
+                Err(ParseDecimalError::Invalid),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(
+            "0.0000000000000000001".parse::<Decimal>(),
+            Err(ParseDecimalError::TooManyFractionDigits)
+        );
+        let overflow = format!("{}1", max.to_string().replace('.', ""));
+        assert_eq!(
+            overflow.parse::<Decimal>(),
+            Err(ParseDecimalError::Overflow)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trip(words in any::<[u64; 3]>()) {
+            let decimal = Decimal(U192(words));
+            prop_assert_eq!(decimal.to_string().parse::<Decimal>(), Ok(decimal));
+        }
+    }
 }
@@ -16,7 +16,7 @@
 #![allow(clippy::reversed_empty_ranges)]
 #![allow(clippy::manual_range_contains)]
 
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, fmt, str::FromStr};
 
 use solana_program::program_error::ProgramError;
 use uint::construct_uint;
@@ -56,6 +56,34 @@ impl Rate {
         Self(U128::from(percent as u64 * PERCENT_SCALER))
     }
 
+    /// Create scaled decimal from basis points, 30 bps = 0.3% = 0.003
+    pub fn from_bps(bps: u16) -> Self {
+        Self(U128::from(bps as u64 * BPS_SCALER))
+    }
+
+    /// Return value in basis points. Fails with `InvalidAmount` if it is not a whole number of
+    /// them and with `MathOverflow` if it exceeds u16.
+    pub fn to_bps(&self) -> Result<u16, ProgramError> {
+        let bps_scaler = U128::from(BPS_SCALER);
+        if !(self.0 % bps_scaler).is_zero() {
+            return Err(FlashError::InvalidAmount.into());
+        }
+        Ok(u16::try_from(self.0 / bps_scaler).map_err(|_| FlashError::MathOverflow)?)
+    }
+
+    /// Parse a percent value exactly, "0.3" = 0.3% = 0.003
+    pub fn from_percent_decimal(percent: &str) -> Result<Self, ParseDecimalError> {
+        let scaled_val = scaled_digits(percent, SCALE - 2)?;
+        Ok(Self(
+            U128::from_dec_str(&scaled_val).map_err(|_| ParseDecimalError::Overflow)?,
+        ))
+    }
+
+    /// Return raw scaled value if it fits within u64, e.g. for `ReserveFees::flash_loan_fee_wad`
+    pub fn to_wad_u64(&self) -> Result<u64, ProgramError> {
+        Ok(u64::try_from(self.0).map_err(|_| FlashError::MathOverflow)?)
+    }
+
     /// Return raw scaled value
     #[allow(clippy::wrong_self_convention)]
     pub fn to_scaled_val(&self) -> u128 {
@@ -63,10 +91,30 @@ impl Rate {
     }
 
     /// Create decimal from scaled value
-    pub fn from_scaled_val(scaled_val: u64) -> Self {
+    pub fn from_scaled_val(scaled_val: u128) -> Self {
         Self(U128::from(scaled_val))
     }
 
+    /// Largest value, use `Ord::min` and `Ord::max` to compare
+    pub fn max_value() -> Self {
+        Self(U128::MAX)
+    }
+
+    /// Add, return the largest value on overflow
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+
+    /// Subtract, return zero on underflow
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Multiply, return the largest value on overflow
+    pub fn saturating_mul(self, rhs: Self) -> Self {
+        self.try_mul(rhs).unwrap_or_else(|_| Self::max_value())
+    }
+
     /// Calculates base^exp
     pub fn try_pow(&self, mut exp: u64) -> Result<Rate, ProgramError> {
         let mut base = *self;
@@ -102,6 +150,18 @@ impl fmt::Display for Rate {
     }
 }
 
+impl FromStr for Rate {
+    type Err = ParseDecimalError;
+
+    /// Parses a decimal number, e.g. "0.003", exactly
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let scaled_val = scaled_digits(s, SCALE)?;
+        Ok(Self(
+            U128::from_dec_str(&scaled_val).map_err(|_| ParseDecimalError::Overflow)?,
+        ))
+    }
+}
+
 impl TryFrom<Decimal> for Rate {
     type Error = ProgramError;
     fn try_from(decimal: Decimal) -> Result<Self, Self::Error> {
@@ -109,6 +169,12 @@ impl TryFrom<Decimal> for Rate {
     }
 }
 
+impl From<u64> for Rate {
+    fn from(val: u64) -> Self {
+        Self(Self::wad() * U128::from(val))
+    }
+}
+
 impl TryAdd for Rate {
     fn try_add(self, rhs: Self) -> Result<Self, ProgramError> {
         Ok(Self(
@@ -125,6 +191,18 @@ impl TrySub for Rate {
     }
 }
 
+impl TryAdd<u64> for Rate {
+    fn try_add(self, rhs: u64) -> Result<Self, ProgramError> {
+        self.try_add(Self::from(rhs))
+    }
+}
+
+impl TrySub<u64> for Rate {
+    fn try_sub(self, rhs: u64) -> Result<Self, ProgramError> {
+        self.try_sub(Self::from(rhs))
+    }
+}
+
 impl TryDiv<u64> for Rate {
     fn try_div(self, rhs: u64) -> Result<Self, ProgramError> {
         Ok(Self(
@@ -147,6 +225,12 @@ impl TryDiv<Rate> for Rate {
     }
 }
 
+impl TryDiv<Decimal> for Rate {
+    fn try_div(self, rhs: Decimal) -> Result<Self, ProgramError> {
+        Self::try_from(Decimal::from(self).try_div(rhs)?)
+    }
+}
+
 impl TryMul<u64> for Rate {
     fn try_mul(self, rhs: u64) -> Result<Self, ProgramError> {
         Ok(Self(
@@ -169,10 +253,173 @@ impl TryMul<Rate> for Rate {
     }
 }
 
+impl TryMul<Decimal> for Rate {
+    fn try_mul(self, rhs: Decimal) -> Result<Self, ProgramError> {
+        Self::try_from(Decimal::from(self).try_mul(rhs)?)
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use proptest::prelude::*;
+
     use super::*;
 
+    fn r(s: &str) -> Rate {
+        s.parse().unwrap()
+    }
+
+    fn overflow<T>() -> Result<T, ProgramError> {
+        Err(FlashError::MathOverflow.into())
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Rate::from(3u64), r("3"));
+        assert_eq!(
+            Rate::from(u64::MAX).to_scaled_val(),
+            u64::MAX as u128 * WAD as u128
+        );
+        assert_eq!(Rate::from_percent(5), r("0.05"));
+        assert_eq!(Rate::from_scaled_val(1), r("0.000000000000000001"));
+        assert_eq!(
+            Rate::try_from("0.3".parse::<Decimal>().unwrap()),
+            Ok(r("0.3"))
+        );
+        assert_eq!(
+            Rate::try_from(Decimal::from(u128::MAX)),
+            Err(FlashError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn bps_and_percent() {
+        assert_eq!(Rate::from_bps(30), r("0.003"));
+        assert_eq!(Rate::from_bps(10_000), Rate::one());
+        assert_eq!(Rate::from_bps(u16::MAX), r("6.5535"));
+        assert_eq!(r("0.003").to_bps(), Ok(30));
+        assert_eq!(Rate::from_bps(u16::MAX).to_bps(), Ok(u16::MAX));
+        assert_eq!(r("0.00025").to_bps(), Err(FlashError::InvalidAmount.into()));
+        assert_eq!(r("6.5536").to_bps(), overflow());
+
+        assert_eq!(Rate::from_percent_decimal("0.3"), Ok(r("0.003")));
+        assert_eq!(Rate::from_percent_decimal("100"), Ok(Rate::one()));
+        assert_eq!(
+            Rate::from_percent_decimal("0.0000000000000001"),
+            Ok(Rate::from_scaled_val(1))
+        );
+        assert_eq!(
+            Rate::from_percent_decimal("0.00000000000000001"),
+            Err(ParseDecimalError::TooManyFractionDigits)
+        );
+        assert_eq!(
+            Rate::from_percent_decimal("0.3%"),
+            Err(ParseDecimalError::Invalid)
+        );
+
+        assert_eq!(Rate::from_bps(30).to_wad_u64(), Ok(3_000_000_000_000_000));
+        assert_eq!(Rate::from(19u64).to_wad_u64(), overflow());
+        assert_eq!(
+            Rate::from_scaled_val(u64::MAX as u128).to_wad_u64(),
+            Ok(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn add_and_sub() {
+        assert_eq!(r("0.5").try_add(r("0.25")), Ok(r("0.75")));
+        assert_eq!(r("0.5").try_add(1u64), Ok(r("1.5")));
+        assert_eq!(
+            Rate::max_value().try_add(r("0.000000000000000001")),
+            overflow()
+        );
+        assert_eq!(Rate::max_value().try_add(1u64), overflow());
+
+        assert_eq!(r("0.5").try_sub(r("0.25")), Ok(r("0.25")));
+        assert_eq!(r("1.5").try_sub(1u64), Ok(r("0.5")));
+        assert_eq!(r("0.5").try_sub(r("0.6")), overflow());
+        assert_eq!(r("0.5").try_sub(1u64), overflow());
+    }
+
+    #[test]
+    fn mul_and_div() {
+        let decimal = |s: &str| s.parse::<Decimal>().unwrap();
+
+        assert_eq!(r("0.5").try_mul(r("0.5")), Ok(r("0.25")));
+        assert_eq!(r("0.5").try_mul(3u64), Ok(r("1.5")));
+        assert_eq!(r("0.5").try_mul(decimal("3")), Ok(r("1.5")));
+        // Symmetric with Decimal * Rate
+        assert_eq!(
+            Decimal::from(r("0.3").try_mul(decimal("1.5")).unwrap()),
+            decimal("1.5").try_mul(r("0.3")).unwrap()
+        );
+        assert_eq!(Rate::max_value().try_mul(r("1")), overflow());
+        assert_eq!(Rate::max_value().try_mul(2u64), overflow());
+        assert_eq!(
+            r("1").try_mul(Decimal::from(u64::MAX).try_mul(100u64).unwrap()),
+            overflow()
+        );
+
+        assert_eq!(r("0.5").try_div(r("0.25")), Ok(r("2")));
+        assert_eq!(r("0.5").try_div(2u64), Ok(r("0.25")));
+        assert_eq!(r("0.5").try_div(decimal("0.25")), Ok(r("2")));
+        assert_eq!(r("1").try_div(r("3")), Ok(r("0.333333333333333333")));
+        assert_eq!(r("1").try_div(Rate::zero()), overflow());
+        assert_eq!(r("1").try_div(0u64), overflow());
+        assert_eq!(r("1").try_div(Decimal::zero()), overflow());
+        assert_eq!(Rate::max_value().try_div(r("1")), overflow());
+        assert_eq!(
+            r("1").try_div(decimal("0.000000000000000001")),
+            Ok(r("1000000000000000000"))
+        );
+    }
+
+    #[test]
+    fn saturating_and_min_max() {
+        let max = Rate::max_value();
+        assert_eq!(r("1").saturating_add(r("2")), r("3"));
+        assert_eq!(max.saturating_add(r("1")), max);
+        assert_eq!(r("2").saturating_sub(r("1")), r("1"));
+        assert_eq!(r("1").saturating_sub(r("2")), Rate::zero());
+        assert_eq!(r("2").saturating_mul(r("0.5")), r("1"));
+        assert_eq!(max.saturating_mul(r("2")), max);
+
+        assert_eq!(r("0.1").min(r("0.2")), r("0.1"));
+        assert_eq!(r("0.1").max(r("0.2")), r("0.2"));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("1".parse(), Ok(Rate::one()));
+        assert_eq!(
+            "0.003".parse(),
+            Ok(Rate::from_scaled_val(3_000_000_000_000_000))
+        );
+        assert_eq!(
+            "0.003000000000000000".parse::<Rate>().unwrap().to_string(),
+            "0.003000000000000000"
+        );
+        assert_eq!(
+            "0.0000000000000000001".parse::<Rate>(),
+            Err(ParseDecimalError::TooManyFractionDigits)
+        );
+        assert_eq!("0,3".parse::<Rate>(), Err(ParseDecimalError::Invalid));
+        let max = Rate(U128::MAX);
+        assert_eq!(max.to_string().parse(), Ok(max));
+        assert_eq!(
+            "340282366920938463464".parse::<Rate>(),
+            Err(ParseDecimalError::Overflow)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trip(scaled_val in any::<u128>()) {
+            let rate = Rate(U128::from(scaled_val));
+            prop_assert_eq!(rate.to_string().parse::<Rate>(), Ok(rate));
+        }
+    }
+
     #[test]
     fn checked_pow() {
         assert_eq!(Rate::one(), Rate::one().try_pow(u64::MAX).unwrap());
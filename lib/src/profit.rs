@@ -0,0 +1,318 @@
+//! Profitability of flash loan strategies
+//!
+//! A strategy borrows `amount`, turns it into `expected_return` of the same token (e.g. by
+//! swaps) and repays `amount` plus the [flash loan fee](../fn.flash_loan_fee.html). The
+//! transaction fee is paid in lamports and converted into the borrowed token with `Prices`.
+//! All accounting is done in `Decimal` so no precision is lost before the result.
+
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::program_error::ProgramError;
+
+use crate::error::{FlashProgramError, FlashSdkError};
+use crate::flash_loan_fee;
+use crate::math::{Decimal, TryAdd, TryDiv, TryMul, TrySub};
+use crate::types::Reserve;
+
+/// Prices of one whole borrowed token and one SOL in the same quote currency, e.g. USD
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Prices {
+    /// Price of one borrowed token
+    pub token: Decimal,
+    /// Price of one SOL
+    pub sol: Decimal,
+}
+
+/// Value which may be negative
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetProfit {
+    /// Strategy earns the value
+    Profit(Decimal),
+    /// Strategy loses the value
+    Loss(Decimal),
+}
+
+impl NetProfit {
+    /// Whether the strategy does not lose anything
+    pub fn is_profitable(&self) -> bool {
+        matches!(self, NetProfit::Profit(_))
+    }
+
+    fn new(income: Decimal, expenses: Decimal) -> Result<Self, FlashSdkError> {
+        Ok(if income >= expenses {
+            NetProfit::Profit(income.try_sub(expenses).map_err(math_error)?)
+        } else {
+            NetProfit::Loss(expenses.try_sub(income).map_err(math_error)?)
+        })
+    }
+}
+
+/// Flash loan strategy to evaluate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Strategy {
+    /// Amount borrowed from the reserve
+    pub amount: u64,
+    /// Amount of the borrowed token the strategy ends up with before repaying
+    pub expected_return: u64,
+    /// Transaction cost in lamports, i.e. signature and priority fees
+    pub transaction_cost: u64,
+    /// Prices to convert the transaction cost into the borrowed token
+    pub prices: Prices,
+}
+
+/// Outcome of a [Strategy](struct.Strategy.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Profitability {
+    /// Flash loan fee
+    pub fee: u64,
+    /// Transaction cost in base units of the borrowed token
+    pub transaction_cost: Decimal,
+    /// `expected_return - amount - fee - transaction_cost` in base units of the borrowed token
+    pub net_profit: NetProfit,
+    /// Net profit in the quote currency of `prices`
+    pub net_profit_value: NetProfit,
+}
+
+impl Strategy {
+    /// Calculates the net profit of borrowing `amount` from `reserve`
+    pub fn profitability(&self, reserve: &Reserve) -> Result<Profitability, FlashSdkError> {
+        let fee = flash_loan_fee(reserve, self.amount)?;
+        let transaction_cost = self.transaction_cost_in_tokens(reserve)?;
+        let expenses = Decimal::from(self.amount)
+            .try_add(fee)
+            .and_then(|expenses| expenses.try_add(transaction_cost))
+            .map_err(math_error)?;
+        let net_profit = NetProfit::new(Decimal::from(self.expected_return), expenses)?;
+
+        let to_value = |amount: Decimal| {
+            amount
+                .try_mul(self.prices.token)
+                .and_then(|value| value.try_div(token_scale(reserve)?))
+                .map_err(math_error)
+        };
+        let net_profit_value = match net_profit {
+            NetProfit::Profit(amount) => NetProfit::Profit(to_value(amount)?),
+            NetProfit::Loss(amount) => NetProfit::Loss(to_value(amount)?),
+        };
+
+        Ok(Profitability {
+            fee,
+            transaction_cost,
+            net_profit,
+            net_profit_value,
+        })
+    }
+
+    /// Returns the amount from which on borrowing from `reserve` is profitable, assuming the
+    /// return grows proportionally to the borrowed amount, i.e. the strategy keeps
+    /// `expected_return / amount`. `None` when the available liquidity is not profitable.
+    ///
+    /// The amount is found by bisection. Since the fee is rounded to whole base units,
+    /// profitability may alternate for amounts close to it.
+    pub fn break_even_amount(&self, reserve: &Reserve) -> Result<Option<u64>, FlashSdkError> {
+        if self.amount == 0 {
+            return Ok(None);
+        }
+        let transaction_cost = self.transaction_cost_in_tokens(reserve)?;
+        let is_profitable = |amount: u64| -> Result<bool, FlashSdkError> {
+            let fee = match flash_loan_fee(reserve, amount) {
+                Ok(fee) => fee,
+                Err(FlashSdkError::FlashError(FlashProgramError::BorrowTooSmall)) => {
+                    return Ok(false)
+                }
+                Err(err) => return Err(err),
+            };
+            let income = Decimal::from(self.expected_return)
+                .try_mul(amount)
+                .and_then(|income| income.try_div(self.amount))
+                .map_err(math_error)?;
+            let expenses = Decimal::from(amount)
+                .try_add(fee)
+                .and_then(|expenses| expenses.try_add(transaction_cost))
+                .map_err(math_error)?;
+            Ok(income >= expenses)
+        };
+
+        let mut high = reserve.liquidity.available_amount;
+        if high == 0 || !is_profitable(high)? {
+            return Ok(None);
+        }
+        let mut low = 0;
+        // Invariant: `high` is profitable, `low` is not
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if is_profitable(middle)? {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+
+        Ok(Some(high))
+    }
+
+    fn transaction_cost_in_tokens(&self, reserve: &Reserve) -> Result<Decimal, FlashSdkError> {
+        Decimal::from(self.transaction_cost)
+            .try_mul(self.prices.sol)
+            .and_then(|cost| cost.try_mul(token_scale(reserve)?))
+            .and_then(|cost| cost.try_div(LAMPORTS_PER_SOL))
+            .and_then(|cost| cost.try_div(self.prices.token))
+            .map_err(math_error)
+    }
+}
+
+/// Base units in one whole token of the reserve liquidity mint
+fn token_scale(reserve: &Reserve) -> Result<Decimal, ProgramError> {
+    Decimal::from(10u64).try_pow(reserve.liquidity.mint_decimals)
+}
+
+fn math_error<E>(_: E) -> FlashSdkError {
+    FlashSdkError::FlashError(FlashProgramError::MathOverflow)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::ReserveBuilder;
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn usdc_reserve() -> Reserve {
+        ReserveBuilder::new()
+            .fee_bps(30)
+            .decimals(6)
+            .available_liquidity(1_000_000_000_000)
+            .build()
+    }
+
+    fn strategy(amount: u64, expected_return: u64) -> Strategy {
+        Strategy {
+            amount,
+            expected_return,
+            transaction_cost: 5_000,
+            prices: Prices {
+                token: d("1"),
+                sol: d("20"),
+            },
+        }
+    }
+
+    #[test]
+    fn profitable_strategy() {
+        let reserve = usdc_reserve();
+        // 0.5% return on 1000 USDC
+        let strategy = strategy(1_000_000_000, 1_005_000_000);
+
+        // 5000 lamports at $20 per SOL are $0.0001 or 100 USDC base units
+        assert_eq!(
+            strategy.profitability(&reserve),
+            Ok(Profitability {
+                fee: 3_000_000,
+                transaction_cost: d("100"),
+                net_profit: NetProfit::Profit(d("1999900")),
+                net_profit_value: NetProfit::Profit(d("1.9999")),
+            })
+        );
+        // 0.2% margin after fee pays the transaction cost of 100 from about 50_000, at 49_800
+        // already since the fee of 149.4 is rounded down
+        assert_eq!(strategy.break_even_amount(&reserve), Ok(Some(49_800)));
+        assert!(strategy
+            .profitability(&reserve)
+            .map(|profitability| profitability.net_profit.is_profitable())
+            .unwrap());
+
+        let break_even = |amount: u64| Strategy {
+            amount,
+            expected_return: amount / 200 * 201,
+            ..strategy
+        };
+        assert_eq!(
+            break_even(49_800)
+                .profitability(&reserve)
+                .unwrap()
+                .net_profit,
+            NetProfit::Profit(Decimal::zero())
+        );
+        assert_eq!(
+            break_even(50_000)
+                .profitability(&reserve)
+                .unwrap()
+                .net_profit,
+            NetProfit::Profit(Decimal::zero())
+        );
+        assert_eq!(
+            break_even(49_000)
+                .profitability(&reserve)
+                .unwrap()
+                .net_profit,
+            NetProfit::Loss(d("2"))
+        );
+    }
+
+    #[test]
+    fn unprofitable_strategy() {
+        let reserve = usdc_reserve();
+        // 0.2% return does not cover the 0.3% fee
+        let strategy = strategy(1_000_000_000, 1_002_000_000);
+
+        let profitability = strategy.profitability(&reserve).unwrap();
+        assert_eq!(profitability.net_profit, NetProfit::Loss(d("1000100")));
+        assert_eq!(profitability.net_profit_value, NetProfit::Loss(d("1.0001")));
+        assert!(!profitability.net_profit.is_profitable());
+        assert_eq!(strategy.break_even_amount(&reserve), Ok(None));
+        assert_eq!(
+            Strategy {
+                amount: 0,
+                ..strategy
+            }
+            .break_even_amount(&reserve),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn break_even_with_minimum_fee() {
+        // No fee share of Texture, so the minimum fee is 1 and the first profitable amount has
+        // to earn it together with the transaction cost
+        let reserve = ReserveBuilder::new()
+            .fee_bps(30)
+            .texture_fee_percentage(0)
+            .decimals(9)
+            .available_liquidity(1_000_000)
+            .build();
+        let strategy = Strategy {
+            amount: 100,
+            expected_return: 110,
+            transaction_cost: 1,
+            prices: Prices {
+                token: d("1"),
+                sol: d("1"),
+            },
+        };
+
+        // At 20 the return of 22 covers 20, the fee of 1 and the cost of 1
+        assert_eq!(strategy.break_even_amount(&reserve), Ok(Some(20)));
+        assert_eq!(
+            strategy.profitability(&reserve).unwrap(),
+            Profitability {
+                fee: 1,
+                transaction_cost: d("1"),
+                net_profit: NetProfit::Profit(d("8")),
+                net_profit_value: NetProfit::Profit(d("0.000000008")),
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_prices() {
+        let reserve = usdc_reserve();
+        let mut strategy = strategy(1_000_000, 1_010_000);
+        strategy.prices.token = Decimal::zero();
+
+        assert_eq!(
+            strategy.profitability(&reserve),
+            Err(FlashSdkError::FlashError(FlashProgramError::MathOverflow))
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Flash loan cost quotes
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use solana_program::clock::Epoch;
+
+use crate::error::{FlashProgramError, FlashSdkError};
+use crate::flash_loan_fee;
+use crate::token::transfer_fee;
+use crate::types::Reserve;
+
+/// What a flash loan of `amount` costs the user, including Token-2022 transfer fees.
+///
+/// SPL Token transfers move exact amounts: the user receives `amount` and `FlashRepay` takes
+/// `amount + fee` back, so only `fee` has to be available on top of the borrowed tokens.
+///
+/// Token-2022 mints with the transfer fee extension withhold a fee from every `transfer_checked`:
+///
+/// * `FlashBorrow` sends `amount` out of the reserve supply, but the user's account is credited
+///   with `received_amount = amount - borrow_transfer_fee`.
+/// * `FlashRepay` debits `repay_amount = amount + fee` from the user's account. Another
+///   `repay_transfer_fee` is withheld from it on the way to the reserve supply and fee receiver.
+///
+/// Hence the user has to top up `top_up_amount = repay_amount - received_amount`, i.e. the flash
+/// loan fee plus the borrow transfer fee, from their own funds. The repay transfer fee is not
+/// debited on top of `repay_amount`, it reduces what the reserve side receives.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FlashLoanQuote {
+    /// Amount borrowed from the reserve
+    pub amount: u64,
+    /// Flash loan fee, see [flash_loan_fee](../fn.flash_loan_fee.html)
+    pub fee: u64,
+    /// Transfer fee withheld from the borrowed amount
+    pub borrow_transfer_fee: u64,
+    /// Amount credited to the user's liquidity account by `FlashBorrow`
+    pub received_amount: u64,
+    /// Amount debited from the user's liquidity account by `FlashRepay`
+    pub repay_amount: u64,
+    /// Transfer fee withheld from the repaid amount
+    pub repay_transfer_fee: u64,
+    /// Amount the user has to hold in the liquidity account in addition to the received amount
+    pub top_up_amount: u64,
+}
+
+impl FlashLoanQuote {
+    /// Quotes a flash loan of a mint without transfer fees
+    pub fn new(reserve: &Reserve, amount: u64) -> Result<Self, FlashSdkError> {
+        Self::build(reserve, amount, |_| Ok(0))
+    }
+
+    /// Quotes a flash loan of a Token-2022 mint with `mint_data` in `epoch`
+    pub fn with_transfer_fee(
+        reserve: &Reserve,
+        amount: u64,
+        mint_data: &[u8],
+        epoch: Epoch,
+    ) -> Result<Self, FlashSdkError> {
+        Self::build(reserve, amount, |amount| {
+            transfer_fee(mint_data, epoch, amount)
+        })
+    }
+
+    fn build(
+        reserve: &Reserve,
+        amount: u64,
+        transfer_fee: impl Fn(u64) -> Result<u64, FlashSdkError>,
+    ) -> Result<Self, FlashSdkError> {
+        let overflow = || FlashSdkError::FlashError(FlashProgramError::MathOverflow);
+
+        let fee = flash_loan_fee(reserve, amount)?;
+        let borrow_transfer_fee = transfer_fee(amount)?;
+        let received_amount = amount
+            .checked_sub(borrow_transfer_fee)
+            .ok_or_else(overflow)?;
+        let repay_amount = amount.checked_add(fee).ok_or_else(overflow)?;
+        let repay_transfer_fee = transfer_fee(repay_amount)?;
+
+        Ok(Self {
+            amount,
+            fee,
+            borrow_transfer_fee,
+            received_amount,
+            repay_amount,
+            repay_transfer_fee,
+            top_up_amount: repay_amount - received_amount,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{mint_with_transfer_fee, ReserveBuilder};
+
+    #[test]
+    fn quote_without_transfer_fee() {
+        let reserve = ReserveBuilder::new()
+            .fee_bps(30)
+            .available_liquidity(u64::MAX)
+            .build();
+
+        assert_eq!(
+            FlashLoanQuote::new(&reserve, 1_000_000),
+            Ok(FlashLoanQuote {
+                amount: 1_000_000,
+                fee: 3_000,
+                borrow_transfer_fee: 0,
+                received_amount: 1_000_000,
+                repay_amount: 1_003_000,
+                repay_transfer_fee: 0,
+                top_up_amount: 3_000,
+            })
+        );
+    }
+
+    #[test]
+    fn quote_with_transfer_fee() {
+        let reserve = ReserveBuilder::new()
+            .fee_bps(30)
+            .available_liquidity(u64::MAX)
+            .build();
+        let mint = mint_with_transfer_fee(6, 100, 1_000_000);
+
+        assert_eq!(
+            FlashLoanQuote::with_transfer_fee(&reserve, 1_000_000, &mint, 0),
+            Ok(FlashLoanQuote {
+                amount: 1_000_000,
+                fee: 3_000,
+                borrow_transfer_fee: 10_000,
+                received_amount: 990_000,
+                repay_amount: 1_003_000,
+                repay_transfer_fee: 10_030,
+                top_up_amount: 13_000,
+            })
+        );
+    }
+}
@@ -0,0 +1,735 @@
+//! Sending and confirming transactions with retries
+//!
+//! [TransactionSender](struct.TransactionSender.html) signs instructions with the latest
+//! blockhash into a legacy or v0 transaction, sends it and waits for the configured commitment.
+//! When the blockhash expires and not even the transaction history knows the transaction, it is
+//! signed again with a new blockhash, and requests rejected with
+//! HTTP 429 (after the retries of the RPC client itself) are repeated after `retry_delay`.
+//! Both count against `max_retries`.
+
+use std::thread;
+use std::time::Duration;
+
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_client::rpc_response::RpcSimulateTransactionResult;
+use solana_program::address_lookup_table_account::AddressLookupTableAccount;
+use solana_program::hash::Hash;
+use solana_program::instruction::Instruction;
+use solana_program::message::{v0, VersionedMessage};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_sdk::signers::Signers;
+use solana_sdk::transaction::{Transaction, TransactionError, VersionedTransaction};
+use solana_transaction_status::{TransactionStatus, UiTransactionEncoding};
+
+use crate::error::{FlashProgramError, FlashSdkError};
+use crate::simulation::decode_transaction_error;
+
+/// How often the confirmation status is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Settings of a [TransactionSender](struct.TransactionSender.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SenderConfig {
+    /// Commitment to wait for, also used for the blockhash and preflight checks
+    pub commitment: CommitmentConfig,
+    /// Send without simulating the transaction first
+    pub skip_preflight: bool,
+    /// Resends after blockhash expiry and repeated rate limited requests in total
+    pub max_retries: usize,
+    /// Delay before repeating a rate limited request
+    pub retry_delay: Duration,
+}
+
+impl Default for SenderConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentConfig::confirmed(),
+            skip_preflight: false,
+            max_retries: 5,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Outcome of a sent transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SendReport {
+    /// Signature of the last sent transaction
+    pub signature: Signature,
+    /// Transaction error of the preflight check or the landed transaction, `None` on success
+    pub error: Option<TransactionError>,
+    /// Index of the failed instruction
+    pub failed_instruction: Option<u8>,
+    /// Flash Loan program error of the failed instruction
+    pub flash_error: Option<FlashProgramError>,
+    /// Program logs of a failed preflight check
+    pub logs: Vec<String>,
+    /// Number of retries used
+    pub retries: usize,
+}
+
+impl SendReport {
+    /// Returns whether the transaction landed without an error
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Sends transactions of a Flash Loan program and decodes its errors
+pub struct TransactionSender<'a> {
+    rpc_client: &'a RpcClient,
+    program_id: Pubkey,
+    config: SenderConfig,
+}
+
+impl<'a> TransactionSender<'a> {
+    /// Creates a sender decoding errors of `program_id`
+    pub fn new(rpc_client: &'a RpcClient, program_id: Pubkey, config: SenderConfig) -> Self {
+        Self {
+            rpc_client,
+            program_id,
+            config,
+        }
+    }
+
+    /// Signs `instructions`, e.g. a flash loan preceded by compute budget instructions, into a
+    /// legacy transaction paid by `payer`, sends it and waits for confirmation.
+    ///
+    /// A failed preflight check or landed transaction is reported in `SendReport`, other
+    /// failures and exhausted retries are errors.
+    pub fn send(
+        &self,
+        payer: &Pubkey,
+        instructions: &[Instruction],
+        signers: &impl Signers,
+    ) -> Result<SendReport, FlashSdkError> {
+        self.send_signed(|blockhash| {
+            let mut transaction = Transaction::new_with_payer(instructions, Some(payer));
+            transaction
+                .try_sign(signers, blockhash)
+                .map_err(|_| FlashSdkError::SigningError)?;
+            Ok(transaction.into())
+        })
+    }
+
+    /// Same as [send](#method.send) with a v0 transaction resolving accounts through
+    /// `lookup_tables`
+    pub fn send_versioned(
+        &self,
+        payer: &Pubkey,
+        instructions: &[Instruction],
+        lookup_tables: &[AddressLookupTableAccount],
+        signers: &impl Signers,
+    ) -> Result<SendReport, FlashSdkError> {
+        self.send_signed(|blockhash| {
+            let message = v0::Message::try_compile(payer, instructions, lookup_tables, blockhash)
+                .map_err(|_| FlashSdkError::TransactionCompileError)?;
+            VersionedTransaction::try_new(VersionedMessage::V0(message), signers)
+                .map_err(|_| FlashSdkError::SigningError)
+        })
+    }
+
+    /// Sends the transaction signed by `sign` with the latest blockhash, signing it again when
+    /// the blockhash expires
+    fn send_signed(
+        &self,
+        sign: impl Fn(Hash) -> Result<VersionedTransaction, FlashSdkError>,
+    ) -> Result<SendReport, FlashSdkError> {
+        let mut retries = 0;
+        loop {
+            let blockhash = self
+                .with_retries(&mut retries, |rpc_client| {
+                    rpc_client
+                        .get_latest_blockhash_with_commitment(self.config.commitment)
+                        .map(|(blockhash, _)| blockhash)
+                        .map_err(RequestError::from)
+                })
+                .map_err(|_| FlashSdkError::RpcError)?;
+            let transaction = sign(blockhash)?;
+
+            match self.send_and_confirm(&mut retries, &transaction, &blockhash)? {
+                Attempt::Processed { error, logs } => {
+                    let (failed_instruction, flash_error) = match &error {
+                        Some(err) => {
+                            decode_transaction_error(&self.program_id, &transaction.message, err)
+                        }
+                        None => (None, None),
+                    };
+                    return Ok(SendReport {
+                        signature: transaction.signatures[0],
+                        error,
+                        failed_instruction,
+                        flash_error,
+                        logs,
+                        retries,
+                    });
+                }
+                Attempt::Expired if retries < self.config.max_retries => retries += 1,
+                Attempt::Expired => {
+                    return Err(FlashSdkError::TransactionError(
+                        TransactionError::BlockhashNotFound,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Sends `transaction` and waits until it reaches the commitment or its blockhash expires
+    fn send_and_confirm(
+        &self,
+        retries: &mut usize,
+        transaction: &VersionedTransaction,
+        blockhash: &Hash,
+    ) -> Result<Attempt, FlashSdkError> {
+        let config = RpcSendTransactionConfig {
+            skip_preflight: self.config.skip_preflight,
+            preflight_commitment: Some(self.config.commitment.commitment),
+            encoding: Some(UiTransactionEncoding::Base64),
+            ..RpcSendTransactionConfig::default()
+        };
+        let signature = match self.with_retries(retries, |rpc_client| {
+            rpc_client
+                .send_transaction_with_config(transaction, config)
+                .map_err(RequestError::from)
+        }) {
+            Ok(signature) => signature,
+            Err(RequestError::PreflightFailure(result)) => {
+                return Ok(match result.err {
+                    Some(TransactionError::BlockhashNotFound) => Attempt::Expired,
+                    error => Attempt::Processed {
+                        error,
+                        logs: result.logs.unwrap_or_default(),
+                    },
+                })
+            }
+            Err(_) => return Err(FlashSdkError::RpcError),
+        };
+
+        loop {
+            let status = self.signature_status(retries, &signature, false)?;
+            match status {
+                Some(status) if status.satisfies_commitment(self.config.commitment) => {
+                    return Ok(Attempt::Processed {
+                        error: status.err,
+                        logs: vec![],
+                    })
+                }
+                // Landed, waiting for the commitment
+                Some(_) => {}
+                None => {
+                    let is_valid = self
+                        .with_retries(retries, |rpc_client| {
+                            rpc_client
+                                .is_blockhash_valid(blockhash, CommitmentConfig::processed())
+                                .map_err(RequestError::from)
+                        })
+                        .map_err(|_| FlashSdkError::RpcError)?;
+                    if !is_valid {
+                        // It may have landed since, or the node may not keep its status anymore
+                        match self.signature_status(retries, &signature, true)? {
+                            Some(status) if status.satisfies_commitment(self.config.commitment) => {
+                                return Ok(Attempt::Processed {
+                                    error: status.err,
+                                    logs: vec![],
+                                })
+                            }
+                            Some(_) => {}
+                            None => return Ok(Attempt::Expired),
+                        }
+                    }
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Status of `signature`, searched in the transaction history of the node if `history`
+    fn signature_status(
+        &self,
+        retries: &mut usize,
+        signature: &Signature,
+        history: bool,
+    ) -> Result<Option<TransactionStatus>, FlashSdkError> {
+        let statuses = self
+            .with_retries(retries, |rpc_client| {
+                if history {
+                    rpc_client.get_signature_statuses_with_history(&[*signature])
+                } else {
+                    rpc_client.get_signature_statuses(&[*signature])
+                }
+                .map_err(RequestError::from)
+            })
+            .map_err(|_| FlashSdkError::RpcError)?;
+
+        Ok(statuses.value.into_iter().next().flatten())
+    }
+
+    /// Calls `request` until it is not rate limited or the retries are exhausted
+    fn with_retries<T>(
+        &self,
+        retries: &mut usize,
+        request: impl Fn(&RpcClient) -> Result<T, RequestError>,
+    ) -> Result<T, RequestError> {
+        loop {
+            match request(self.rpc_client) {
+                Err(RequestError::RateLimited) if *retries < self.config.max_retries => {
+                    *retries += 1;
+                    thread::sleep(self.config.retry_delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Outcome of sending a transaction with one blockhash
+enum Attempt {
+    /// Rejected by the preflight check or reached the commitment
+    Processed {
+        error: Option<TransactionError>,
+        logs: Vec<String>,
+    },
+    /// Blockhash expired before the transaction landed
+    Expired,
+}
+
+/// RPC request failure relevant for retries
+enum RequestError {
+    /// HTTP 429 Too Many Requests
+    RateLimited,
+    PreflightFailure(Box<RpcSimulateTransactionResult>),
+    Other,
+}
+
+impl From<ClientError> for RequestError {
+    fn from(err: ClientError) -> Self {
+        match err.kind {
+            ClientErrorKind::Reqwest(err)
+                if err.status().map(|status| status.as_u16()) == Some(429) =>
+            {
+                RequestError::RateLimited
+            }
+            ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+                ..
+            }) => RequestError::PreflightFailure(Box::new(result)),
+            _ => RequestError::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::{json, Value};
+    use solana_program::instruction::AccountMeta;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    use super::*;
+    use crate::FLASH_LOAN_ID;
+
+    enum Reply {
+        Result(Value),
+        Error(Value),
+        RateLimited,
+    }
+
+    /// Stand-in for the RPC node answering JSON-RPC requests over HTTP with `reply`.
+    /// Returns its URL and the received requests.
+    fn rpc_node(
+        reply: impl FnMut(&str, &Value) -> Reply + Send + 'static,
+    ) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let reply = Arc::new(Mutex::new(reply));
+
+        let server_requests = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (requests, reply) = (server_requests.clone(), reply.clone());
+                thread::spawn(move || serve(stream.unwrap(), &requests, &reply));
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn serve(
+        stream: TcpStream,
+        requests: &Mutex<Vec<Value>>,
+        reply: &Mutex<impl FnMut(&str, &Value) -> Reply>,
+    ) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        // Requests of a keep-alive connection
+        loop {
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let line = line.trim_end().to_ascii_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(length) = line.strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+
+            let method = request["method"].as_str().unwrap();
+            let reply = match method {
+                // Asked by the RPC client to pick request formats
+                "getVersion" => Reply::Result(json!({"solana-core": "1.14.10"})),
+                method => {
+                    requests.lock().unwrap().push(request.clone());
+                    (reply.lock().unwrap())(method, &request["params"])
+                }
+            };
+            let body = match reply {
+                Reply::Result(result) => {
+                    json!({"jsonrpc": "2.0", "result": result, "id": request["id"]})
+                }
+                Reply::Error(error) => {
+                    json!({"jsonrpc": "2.0", "error": error, "id": request["id"]})
+                }
+                Reply::RateLimited => {
+                    stream
+                        .write_all(b"HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\n\r\n")
+                        .unwrap();
+                    continue;
+                }
+            }
+            .to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    }
+
+    fn with_context(value: Value) -> Reply {
+        Reply::Result(json!({"context": {"slot": 1}, "value": value}))
+    }
+
+    fn latest_blockhash() -> Reply {
+        with_context(json!({
+            "blockhash": Hash::new_unique().to_string(),
+            "lastValidBlockHeight": 100,
+        }))
+    }
+
+    /// Signature of a base64 encoded transaction with one signer
+    fn signature(params: &Value) -> Reply {
+        let transaction = base64::decode(params[0].as_str().unwrap()).unwrap();
+        Reply::Result(json!(bs58::encode(&transaction[1..65]).into_string()))
+    }
+
+    fn status(confirmation_status: &str, err: Value) -> Reply {
+        let status = if err.is_null() {
+            json!({"Ok": null})
+        } else {
+            json!({ "Err": err })
+        };
+        with_context(json!([{
+            "slot": 1,
+            "confirmations": null,
+            "err": err,
+            "status": status,
+            "confirmationStatus": confirmation_status,
+        }]))
+    }
+
+    fn preflight_failure(err: Value) -> Reply {
+        Reply::Error(json!({
+            "code": -32002,
+            "message": "Transaction simulation failed",
+            "data": {"err": err, "logs": ["Program log: failed"], "accounts": null},
+        }))
+    }
+
+    fn methods(requests: &Mutex<Vec<Value>>) -> Vec<String> {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request["method"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn send(url: String, config: SenderConfig) -> Result<SendReport, FlashSdkError> {
+        let rpc_client = RpcClient::new(url);
+        let payer = Keypair::new();
+        let instructions = [
+            Instruction::new_with_bytes(spl_token::id(), &[], vec![]),
+            Instruction::new_with_bytes(FLASH_LOAN_ID, &[], vec![]),
+        ];
+        TransactionSender::new(&rpc_client, FLASH_LOAN_ID, config).send(
+            &payer.pubkey(),
+            &instructions,
+            &[&payer],
+        )
+    }
+
+    #[test]
+    fn wait_for_commitment() {
+        let mut statuses = 0;
+        let (url, requests) = rpc_node(move |method, params| match method {
+            "getLatestBlockhash" => latest_blockhash(),
+            "sendTransaction" => signature(params),
+            "isBlockhashValid" => with_context(json!(true)),
+            "getSignatureStatuses" => {
+                statuses += 1;
+                match statuses {
+                    1 => with_context(json!([null])),
+                    2 => status("processed", Value::Null),
+                    _ => status("confirmed", Value::Null),
+                }
+            }
+            _ => unreachable!("{}", method),
+        });
+
+        let report = send(url, SenderConfig::default()).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.retries, 0);
+        assert_eq!(
+            methods(&requests),
+            vec![
+                "getLatestBlockhash",
+                "sendTransaction",
+                "getSignatureStatuses",
+                "isBlockhashValid",
+                "getSignatureStatuses",
+                "getSignatureStatuses",
+            ]
+        );
+        let send_config = &requests.lock().unwrap()[1]["params"][1];
+        assert_eq!(send_config["skipPreflight"], false);
+        assert_eq!(send_config["preflightCommitment"], "confirmed");
+    }
+
+    #[test]
+    fn resend_with_new_blockhash() {
+        let mut sent = 0;
+        let (url, requests) = rpc_node(move |method, params| match method {
+            "getLatestBlockhash" => latest_blockhash(),
+            "sendTransaction" => {
+                sent += 1;
+                match sent {
+                    1 => preflight_failure(json!("BlockhashNotFound")),
+                    _ => signature(params),
+                }
+            }
+            "isBlockhashValid" => with_context(json!(sent > 2)),
+            "getSignatureStatuses" if sent < 3 => with_context(json!([null])),
+            "getSignatureStatuses" => status("finalized", Value::Null),
+            _ => unreachable!("{}", method),
+        });
+
+        let report = send(url, SenderConfig::default()).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.retries, 2);
+        let statuses = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request["method"] == "getSignatureStatuses")
+            .map(|request| request["params"].clone())
+            .collect::<Vec<_>>();
+        // The expired transaction is searched in the history before resending
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(statuses[1][0], statuses[0][0]);
+        assert_eq!(statuses[1][1], json!({"searchTransactionHistory": true}));
+        assert_ne!(statuses[2][0], statuses[0][0]);
+        assert_eq!(
+            statuses[2][0][0].as_str().unwrap(),
+            report.signature.to_string()
+        );
+
+        let (url, _) = rpc_node(|method, _| match method {
+            "getLatestBlockhash" => latest_blockhash(),
+            "sendTransaction" => preflight_failure(json!("BlockhashNotFound")),
+            _ => unreachable!("{}", method),
+        });
+        assert_eq!(
+            send(
+                url,
+                SenderConfig {
+                    max_retries: 0,
+                    ..SenderConfig::default()
+                }
+            ),
+            Err(FlashSdkError::TransactionError(
+                TransactionError::BlockhashNotFound
+            ))
+        );
+    }
+
+    #[test]
+    fn landed_before_expiry() {
+        let (url, requests) = rpc_node(move |method, params| match method {
+            "getLatestBlockhash" => latest_blockhash(),
+            "sendTransaction" => signature(params),
+            "isBlockhashValid" => with_context(json!(false)),
+            // Only the transaction history knows the landed transaction
+            "getSignatureStatuses" if params[1].is_null() => with_context(json!([null])),
+            "getSignatureStatuses" => status("confirmed", Value::Null),
+            _ => unreachable!("{}", method),
+        });
+
+        let report = send(url, SenderConfig::default()).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.retries, 0);
+        assert_eq!(
+            methods(&requests),
+            vec![
+                "getLatestBlockhash",
+                "sendTransaction",
+                "getSignatureStatuses",
+                "isBlockhashValid",
+                "getSignatureStatuses",
+            ]
+        );
+        assert_eq!(
+            requests.lock().unwrap()[4]["params"][1]["searchTransactionHistory"],
+            true
+        );
+    }
+
+    #[test]
+    fn send_versioned_transaction() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let server_sent = sent.clone();
+        let (url, _) = rpc_node(move |method, params| match method {
+            "getLatestBlockhash" => latest_blockhash(),
+            "sendTransaction" => {
+                server_sent
+                    .lock()
+                    .unwrap()
+                    .push(base64::decode(params[0].as_str().unwrap()).unwrap());
+                signature(params)
+            }
+            "getSignatureStatuses" => status("confirmed", Value::Null),
+            _ => unreachable!("{}", method),
+        });
+        let rpc_client = RpcClient::new(url);
+        let payer = Keypair::new();
+        let account = Pubkey::new_unique();
+        let lookup_table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![account],
+        };
+        let instructions = [Instruction::new_with_bytes(
+            FLASH_LOAN_ID,
+            &[],
+            vec![AccountMeta::new_readonly(account, false)],
+        )];
+
+        let report = TransactionSender::new(&rpc_client, FLASH_LOAN_ID, SenderConfig::default())
+            .send_versioned(&payer.pubkey(), &instructions, &[lookup_table], &[&payer])
+            .unwrap();
+        assert!(report.is_success());
+        let sent = sent.lock().unwrap();
+        // One signature, then the v0 message prefix
+        assert_eq!(sent[0][65], 0x80);
+        // The account is only referenced through the lookup table
+        assert!(!sent[0].windows(32).any(|window| window == account.as_ref()));
+    }
+
+    #[test]
+    fn retry_rate_limited_requests() {
+        // The RPC client itself retries 5 times
+        let mut rate_limited = 0;
+        let (url, requests) = rpc_node(move |method, params| match method {
+            "getLatestBlockhash" if rate_limited < 6 => {
+                rate_limited += 1;
+                Reply::RateLimited
+            }
+            "getLatestBlockhash" => latest_blockhash(),
+            "sendTransaction" => signature(params),
+            "getSignatureStatuses" => status("confirmed", Value::Null),
+            _ => unreachable!("{}", method),
+        });
+        let config = SenderConfig {
+            retry_delay: Duration::ZERO,
+            ..SenderConfig::default()
+        };
+
+        let report = send(url, config).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.retries, 1);
+        assert_eq!(methods(&requests).len(), 9);
+
+        let (url, _) = rpc_node(|_, _| Reply::RateLimited);
+        assert_eq!(
+            send(
+                url,
+                SenderConfig {
+                    max_retries: 0,
+                    ..config
+                }
+            ),
+            Err(FlashSdkError::RpcError)
+        );
+    }
+
+    #[test]
+    fn decode_flash_error() {
+        let custom = json!({"InstructionError": [1, {"Custom": FlashProgramError::NoFlashRepayFound as u32}]});
+        let preflight_err = custom.clone();
+        let (url, _) = rpc_node(move |method, _| match method {
+            "getLatestBlockhash" => latest_blockhash(),
+            "sendTransaction" => preflight_failure(preflight_err.clone()),
+            _ => unreachable!("{}", method),
+        });
+
+        let report = send(url, SenderConfig::default()).unwrap();
+        assert!(!report.is_success());
+        assert_eq!(report.failed_instruction, Some(1));
+        assert_eq!(
+            report.flash_error,
+            Some(FlashProgramError::NoFlashRepayFound)
+        );
+        assert_eq!(report.logs, vec!["Program log: failed"]);
+
+        // Without preflight the error comes with the landed transaction
+        let (url, requests) = rpc_node(move |method, params| match method {
+            "getLatestBlockhash" => latest_blockhash(),
+            "sendTransaction" => signature(params),
+            "getSignatureStatuses" => status("confirmed", custom.clone()),
+            _ => unreachable!("{}", method),
+        });
+        let report = send(
+            url,
+            SenderConfig {
+                skip_preflight: true,
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            report.flash_error,
+            Some(FlashProgramError::NoFlashRepayFound)
+        );
+        assert!(report.logs.is_empty());
+        assert_eq!(
+            requests.lock().unwrap()[1]["params"][1]["skipPreflight"],
+            true
+        );
+    }
+}
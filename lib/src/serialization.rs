@@ -0,0 +1,152 @@
+//! Serde support behind the `serde` feature
+//!
+//! Pubkeys are serialized as base58 strings and [Decimal](../math/struct.Decimal.html) and
+//! [Rate](../math/struct.Rate.html) as exact decimal strings, e.g. `"0.003000000000000000"`.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::math::{Decimal, Rate};
+
+/// Pubkey as a base58 string, use with `#[serde(with = "flash_loan_sdk::serialization::pubkey")]`
+pub mod pubkey {
+    use std::str::FromStr;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use solana_program::pubkey::Pubkey;
+
+    /// Serializes `pubkey` as a base58 string
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    /// Deserializes a pubkey from a base58 string
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let pubkey = String::deserialize(deserializer)?;
+        Pubkey::from_str(&pubkey).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::instruction::FlashLoanInstruction;
+    use crate::quote::FlashLoanQuote;
+    use crate::testing::ReserveBuilder;
+    use crate::types::Reserve;
+
+    #[test]
+    fn serialize_decimal_and_rate() {
+        let rate = Rate::from_scaled_val(3_000_000_000_000_000);
+        assert_eq!(
+            serde_json::to_value(rate).unwrap(),
+            json!("0.003000000000000000")
+        );
+        assert_eq!(
+            serde_json::from_value::<Rate>(json!("0.003")).unwrap(),
+            rate
+        );
+
+        let decimal = Decimal::from(u64::MAX);
+        assert_eq!(
+            serde_json::to_value(decimal).unwrap(),
+            json!("18446744073709551615.000000000000000000")
+        );
+        assert_eq!(
+            serde_json::from_value::<Decimal>(serde_json::to_value(decimal).unwrap()).unwrap(),
+            decimal
+        );
+
+        assert!(serde_json::from_value::<Rate>(json!(0.003)).is_err());
+        assert!(serde_json::from_value::<Rate>(json!("0.0000000000000000001")).is_err());
+    }
+
+    #[test]
+    fn serialize_reserve() {
+        let mint = Pubkey::new_unique();
+        let fee_receiver = Pubkey::new_unique();
+        let reserve = ReserveBuilder::new()
+            .mint(mint)
+            .fee_receiver(fee_receiver)
+            .build();
+
+        let value = serde_json::to_value(reserve).unwrap();
+        assert_eq!(value["liquidity"]["mint_pubkey"], json!(mint.to_string()));
+        assert_eq!(
+            value["config"]["fee_receiver"],
+            json!(fee_receiver.to_string())
+        );
+        assert_eq!(
+            value["config"]["fees"],
+            json!({
+                "flash_loan_fee_wad": reserve.config.fees.flash_loan_fee_wad,
+                "texture_fee_percentage": reserve.config.fees.texture_fee_percentage,
+            })
+        );
+        let keys = |value: &Value| {
+            value
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert!(keys(&value).iter().all(|key| !key.starts_with('_')));
+        assert!(keys(&value["config"])
+            .iter()
+            .all(|key| !key.starts_with('_')));
+
+        assert_eq!(serde_json::from_value::<Reserve>(value).unwrap(), reserve);
+    }
+
+    #[test]
+    fn serialize_instruction_and_quote() {
+        let instruction = FlashLoanInstruction::FlashBorrow { amount: 10 };
+        let value = serde_json::to_value(&instruction).unwrap();
+        assert_eq!(value, json!({"FlashBorrow": {"amount": 10}}));
+        assert_eq!(
+            serde_json::from_value::<FlashLoanInstruction>(value).unwrap(),
+            instruction
+        );
+
+        let quote = FlashLoanQuote::new(&ReserveBuilder::new().build(), 1_000_000_000).unwrap();
+        let value = serde_json::to_value(quote).unwrap();
+        assert_eq!(value["fee"], json!(quote.fee));
+        assert_eq!(
+            serde_json::from_value::<FlashLoanQuote>(value).unwrap(),
+            quote
+        );
+    }
+}
@@ -0,0 +1,425 @@
+//! Transaction simulation with decoded flash loan outcome
+//!
+//! The simulation RPC only returns account states after the transaction. Balances before it are
+//! read with `getMultipleAccounts` at the same slot the simulation runs on, so balance changes do
+//! not include what other transactions changed in between.
+
+use num_traits::FromPrimitive;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig,
+};
+use solana_client::rpc_response::RpcSimulateTransactionResult;
+use solana_program::instruction::InstructionError;
+use solana_program::message::VersionedMessage;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use solana_sdk::transaction::{TransactionError, VersionedTransaction};
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::error::{FlashProgramError, FlashSdkError};
+
+/// Outcome of a simulated flash loan transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulationReport {
+    /// Transaction error, `None` when the simulation succeeded
+    pub error: Option<TransactionError>,
+    /// Index of the failed instruction
+    pub failed_instruction: Option<u8>,
+    /// Flash Loan program error of the failed instruction
+    pub flash_error: Option<FlashProgramError>,
+    /// Compute units consumed by the transaction
+    pub units_consumed: Option<u64>,
+    /// Token balance change of the reserve fee receiver, `None` when a balance is unavailable
+    pub fee_receiver_balance_change: Option<i128>,
+    /// Token balance change of the user's liquidity account, `None` when a balance is unavailable
+    pub user_balance_change: Option<i128>,
+    /// Program logs
+    pub logs: Vec<String>,
+}
+
+impl SimulationReport {
+    /// Returns whether the simulated transaction succeeded
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Builds the report from the simulation `result` of a transaction with `message`.
+    /// `result` should include the post-simulation fee receiver and user liquidity accounts, in
+    /// this order, and `pre_balances` their token balances before the simulation. The RPC omits
+    /// post-simulation accounts when the transaction fails.
+    pub fn new(
+        program_id: &Pubkey,
+        message: &VersionedMessage,
+        result: RpcSimulateTransactionResult,
+        pre_balances: Option<[u64; 2]>,
+    ) -> Result<Self, FlashSdkError> {
+        let post_balance = |index: usize| -> Result<Option<u64>, FlashSdkError> {
+            match result
+                .accounts
+                .as_ref()
+                .and_then(|accounts| accounts.get(index))
+            {
+                Some(Some(ui_account)) => token_balance(Some(
+                    &ui_account
+                        .decode::<Account>()
+                        .ok_or(FlashSdkError::DeserializationError)?,
+                ))
+                .map(Some),
+                // The account does not exist after the transaction
+                Some(None) => Ok(Some(0)),
+                None => Ok(None),
+            }
+        };
+        let balance_change = |index: usize| -> Result<Option<i128>, FlashSdkError> {
+            Ok(post_balance(index)?
+                .zip(pre_balances)
+                .map(|(post, pre)| post as i128 - pre[index] as i128))
+        };
+        let fee_receiver_balance_change = balance_change(0)?;
+        let user_balance_change = balance_change(1)?;
+
+        let (failed_instruction, flash_error) = match &result.err {
+            Some(err) => decode_transaction_error(program_id, message, err),
+            None => (None, None),
+        };
+
+        Ok(Self {
+            error: result.err,
+            failed_instruction,
+            flash_error,
+            units_consumed: result.units_consumed,
+            fee_receiver_balance_change,
+            user_balance_change,
+            logs: result.logs.unwrap_or_default(),
+        })
+    }
+}
+
+/// How many times balances before the simulation are read to land on the simulated slot
+const MAX_SLOT_ATTEMPTS: usize = 3;
+
+/// Simulates `transaction` and reports its outcome, including balance changes of `fee_receiver`
+/// and the user's `liquidity_account`. Signatures are not verified.
+///
+/// Balance changes are `None` when the balances before the simulation could not be read at the
+/// simulated slot in a few attempts, or the simulation returned no accounts.
+pub fn simulate_flash_loan(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    transaction: &VersionedTransaction,
+    fee_receiver: &Pubkey,
+    liquidity_account: &Pubkey,
+) -> Result<SimulationReport, FlashSdkError> {
+    let commitment = rpc_client.commitment();
+    let mut min_context_slot = None;
+    let mut attempt = 1;
+    loop {
+        let pre_accounts = rpc_client
+            .get_multiple_accounts_with_config(
+                &[*fee_receiver, *liquidity_account],
+                RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(commitment),
+                    min_context_slot,
+                    ..RpcAccountInfoConfig::default()
+                },
+            )
+            .map_err(|_| FlashSdkError::RpcError)?;
+        let pre_slot = pre_accounts.context.slot;
+
+        let result = rpc_client
+            .simulate_transaction_with_config(
+                transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    commitment: Some(commitment),
+                    accounts: Some(RpcSimulateTransactionAccountsConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        addresses: vec![fee_receiver.to_string(), liquidity_account.to_string()],
+                    }),
+                    min_context_slot: Some(pre_slot),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+            .map_err(|_| FlashSdkError::RpcError)?;
+
+        // The bank moved on between the two requests, read balances again at the simulated slot
+        if result.context.slot != pre_slot && attempt < MAX_SLOT_ATTEMPTS {
+            min_context_slot = Some(result.context.slot);
+            attempt += 1;
+            continue;
+        }
+        let pre_balances = if result.context.slot == pre_slot {
+            Some([
+                token_balance(pre_accounts.value[0].as_ref())?,
+                token_balance(pre_accounts.value[1].as_ref())?,
+            ])
+        } else {
+            None
+        };
+        return SimulationReport::new(program_id, &transaction.message, result.value, pre_balances);
+    }
+}
+
+/// Returns the index of the instruction which failed with `err` and its Flash Loan program error,
+/// if the instruction belongs to `program_id` and the error is a known custom error.
+pub fn decode_transaction_error(
+    program_id: &Pubkey,
+    message: &VersionedMessage,
+    err: &TransactionError,
+) -> (Option<u8>, Option<FlashProgramError>) {
+    match err {
+        TransactionError::InstructionError(index, instruction_error) => {
+            let flash_error = match instruction_error {
+                InstructionError::Custom(code) => message
+                    .instructions()
+                    .get(*index as usize)
+                    .filter(|instruction| {
+                        instruction.program_id(message.static_account_keys()) == program_id
+                    })
+                    .and_then(|_| FlashProgramError::from_u32(*code)),
+                _ => None,
+            };
+            (Some(*index), flash_error)
+        }
+        _ => (None, None),
+    }
+}
+
+/// Token amount of a token account, zero when it does not exist
+fn token_balance(account: Option<&Account>) -> Result<u64, FlashSdkError> {
+    match account {
+        Some(account)
+            if account.owner == spl_token::id() || account.owner == spl_token_2022::id() =>
+        {
+            Ok(
+                StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+                    .map_err(|_| FlashSdkError::DeserializationError)?
+                    .base
+                    .amount,
+            )
+        }
+        Some(_) => Err(FlashSdkError::DeserializationError),
+        None => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+    use solana_account_decoder::UiAccount;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_program::instruction::Instruction;
+    use solana_program::message::Message;
+    use solana_program::program_pack::Pack;
+    use solana_sdk::signature::Signature;
+    use spl_token::state::AccountState;
+
+    use super::*;
+    use crate::FLASH_LOAN_ID;
+
+    fn token_account(amount: u64) -> Account {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            amount,
+            state: AccountState::Initialized,
+            ..spl_token::state::Account::default()
+        }
+        .pack_into_slice(&mut data);
+        Account {
+            lamports: 1,
+            data,
+            owner: spl_token::id(),
+            ..Account::default()
+        }
+    }
+
+    fn message() -> VersionedMessage {
+        let payer = Pubkey::new_unique();
+        VersionedMessage::Legacy(Message::new(
+            &[
+                Instruction::new_with_bytes(spl_token::id(), &[], vec![]),
+                Instruction::new_with_bytes(FLASH_LOAN_ID, &[], vec![]),
+            ],
+            Some(&payer),
+        ))
+    }
+
+    #[test]
+    fn successful_simulation() {
+        let accounts = [token_account(3_000), token_account(4_000)]
+            .iter()
+            .map(|account| {
+                Some(UiAccount::encode(
+                    &Pubkey::new_unique(),
+                    account,
+                    UiAccountEncoding::Base64,
+                    None,
+                    None,
+                ))
+            })
+            .collect();
+        let result = RpcSimulateTransactionResult {
+            err: None,
+            logs: Some(vec!["Program log: Instruction: Flash Borrow".to_string()]),
+            accounts: Some(accounts),
+            units_consumed: Some(42_000),
+            return_data: None,
+        };
+
+        let report =
+            SimulationReport::new(&FLASH_LOAN_ID, &message(), result, Some([0, 10_000])).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.failed_instruction, None);
+        assert_eq!(report.flash_error, None);
+        assert_eq!(report.units_consumed, Some(42_000));
+        assert_eq!(report.fee_receiver_balance_change, Some(3_000));
+        assert_eq!(report.user_balance_change, Some(-6_000));
+        assert_eq!(report.logs.len(), 1);
+    }
+
+    #[test]
+    fn failed_simulation() {
+        let result = RpcSimulateTransactionResult {
+            err: Some(TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(FlashProgramError::NoFlashRepayFound as u32),
+            )),
+            logs: None,
+            accounts: None,
+            units_consumed: Some(10_000),
+            return_data: None,
+        };
+
+        let report =
+            SimulationReport::new(&FLASH_LOAN_ID, &message(), result, Some([2_000, 10_000]))
+                .unwrap();
+        assert!(!report.is_success());
+        assert_eq!(report.failed_instruction, Some(1));
+        assert_eq!(
+            report.flash_error,
+            Some(FlashProgramError::NoFlashRepayFound)
+        );
+        // Without post-simulation accounts the balances are unknown, not drained
+        assert_eq!(report.fee_receiver_balance_change, None);
+        assert_eq!(report.user_balance_change, None);
+    }
+
+    #[test]
+    fn closed_account_and_unknown_pre_balances() {
+        let result = || RpcSimulateTransactionResult {
+            err: None,
+            logs: None,
+            accounts: Some(vec![
+                Some(UiAccount::encode(
+                    &Pubkey::new_unique(),
+                    &token_account(3_000),
+                    UiAccountEncoding::Base64,
+                    None,
+                    None,
+                )),
+                None,
+            ]),
+            units_consumed: None,
+            return_data: None,
+        };
+
+        let report =
+            SimulationReport::new(&FLASH_LOAN_ID, &message(), result(), Some([1_000, 10_000]))
+                .unwrap();
+        assert_eq!(report.fee_receiver_balance_change, Some(2_000));
+        assert_eq!(report.user_balance_change, Some(-10_000));
+
+        let report = SimulationReport::new(&FLASH_LOAN_ID, &message(), result(), None).unwrap();
+        assert_eq!(report.fee_receiver_balance_change, None);
+        assert_eq!(report.user_balance_change, None);
+    }
+
+    #[test]
+    fn simulate_with_balances_at_simulated_slot() {
+        let encode = |amount: u64| {
+            Some(UiAccount::encode(
+                &Pubkey::new_unique(),
+                &token_account(amount),
+                UiAccountEncoding::Base64,
+                None,
+                None,
+            ))
+        };
+        let mut mocks = HashMap::new();
+        mocks.insert(
+            RpcRequest::GetMultipleAccounts,
+            json!({
+                "context": { "slot": 5 },
+                "value": [encode(1_000), encode(10_000)],
+            }),
+        );
+        mocks.insert(
+            RpcRequest::SimulateTransaction,
+            json!({
+                "context": { "slot": 5 },
+                "value": RpcSimulateTransactionResult {
+                    err: None,
+                    logs: None,
+                    accounts: Some(vec![encode(4_000), encode(4_000)]),
+                    units_consumed: None,
+                    return_data: None,
+                },
+            }),
+        );
+        let rpc_client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        let transaction = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message: message(),
+        };
+
+        let report = simulate_flash_loan(
+            &rpc_client,
+            &FLASH_LOAN_ID,
+            &transaction,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        )
+        .unwrap();
+        assert_eq!(report.fee_receiver_balance_change, Some(3_000));
+        assert_eq!(report.user_balance_change, Some(-6_000));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let message = message();
+
+        assert_eq!(
+            decode_transaction_error(
+                &FLASH_LOAN_ID,
+                &message,
+                &TransactionError::InstructionError(0, InstructionError::Custom(1))
+            ),
+            (Some(0), None)
+        );
+        assert_eq!(
+            decode_transaction_error(
+                &FLASH_LOAN_ID,
+                &message,
+                &TransactionError::InstructionError(1, InstructionError::Custom(23))
+            ),
+            (Some(1), Some(FlashProgramError::BorrowTooSmall))
+        );
+        assert_eq!(
+            decode_transaction_error(
+                &FLASH_LOAN_ID,
+                &message,
+                &TransactionError::InstructionError(1, InstructionError::Custom(1_000))
+            ),
+            (Some(1), None)
+        );
+        assert_eq!(
+            decode_transaction_error(&FLASH_LOAN_ID, &message, &TransactionError::AccountNotFound),
+            (None, None)
+        );
+    }
+}
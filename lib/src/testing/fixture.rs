@@ -0,0 +1,700 @@
+//! `solana-program-test` fixture with a lending market and a funded reserve
+
+use std::path::PathBuf;
+
+use solana_program::instruction::Instruction;
+use solana_program::program_option::COption;
+use solana_program::program_pack::Pack;
+use solana_program::pubkey::{Pubkey, PUBKEY_BYTES};
+use solana_program::rent::Rent;
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::bpf_loader;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use spl_token::native_mint;
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use spl_token_2022::extension::transfer_fee::TransferFeeAmount;
+use spl_token_2022::extension::{ExtensionType, StateWithExtensions, StateWithExtensionsMut};
+
+use crate::instruction::{flash_borrow_with_token_program, flash_repay_with_token_program};
+use crate::types::Reserve;
+use crate::FLASH_LOAN_ID;
+
+use super::{mint_with_transfer_fee, mock_program, mock_receiver, ReserveBuilder};
+
+/// Parameters of the environment created by [FlashLoanFixture](struct.FlashLoanFixture.html)
+#[derive(Clone, Debug)]
+pub struct FlashLoanFixtureConfig {
+    /// Flash Loan program id
+    pub program_id: Pubkey,
+    /// Path to the Flash Loan program shared object. The Rust mock is used when `None`.
+    pub program_so: Option<PathBuf>,
+    /// Raw lending market account data. Ignored by the mock, which only checks the owner.
+    pub lending_market_data: Vec<u8>,
+    /// Use the native mint (wrapped SOL) as the reserve liquidity mint
+    pub native_mint: bool,
+    /// Reserve liquidity mint decimals, ignored for the native mint
+    pub mint_decimals: u8,
+    /// Liquidity available in the reserve, in native units
+    pub reserve_liquidity: u64,
+    /// Total flash loan fee in basis points
+    pub fee_bps: u16,
+    /// Part of the flash loan fee going to the fee receiver, in %
+    pub texture_fee_percentage: u8,
+    /// Reserve deposit limit, in native units
+    pub deposit_limit: u64,
+    /// Initial balance of the user's liquidity token account, in native units
+    pub user_liquidity: u64,
+    /// Makes the reserve liquidity a Token-2022 mint withholding this many basis points of every
+    /// transfer, without a maximum fee. Ignored for the native mint.
+    pub transfer_fee_bps: Option<u16>,
+}
+
+impl Default for FlashLoanFixtureConfig {
+    fn default() -> Self {
+        Self {
+            program_id: FLASH_LOAN_ID,
+            program_so: None,
+            lending_market_data: vec![],
+            native_mint: false,
+            mint_decimals: 9,
+            reserve_liquidity: 1_000_000_000_000,
+            fee_bps: 30,
+            texture_fee_percentage: 20,
+            deposit_limit: u64::MAX,
+            user_liquidity: 10_000_000_000,
+            transfer_fee_bps: None,
+        }
+    }
+}
+
+/// Running `solana-program-test` bank with the Flash Loan program, a lending market, a funded
+/// reserve, a user keypair with a funded liquidity token account and the
+/// [mock receiver](../mock_receiver/index.html) program for CPI flash loans.
+pub struct FlashLoanFixture {
+    /// Test context, `context.payer` pays for all transactions
+    pub context: ProgramTestContext,
+    /// Flash Loan program id
+    pub program_id: Pubkey,
+    /// Mock flash loan receiver program id
+    pub receiver_program_id: Pubkey,
+    /// Lending market address
+    pub lending_market: Pubkey,
+    /// Derived lending market authority
+    pub lending_market_authority: Pubkey,
+    /// Reserve address
+    pub reserve_pubkey: Pubkey,
+    /// Reserve liquidity mint
+    pub mint: Pubkey,
+    /// Token program owning the reserve liquidity mint
+    pub token_program: Pubkey,
+    /// Reserve liquidity supply token account
+    pub supply: Pubkey,
+    /// Reserve fee receiver token account
+    pub fee_receiver: Pubkey,
+    /// User's wallet and transfer authority
+    pub user: Keypair,
+    /// User's liquidity token account
+    pub user_liquidity: Pubkey,
+}
+
+impl FlashLoanFixture {
+    /// Boots the bank with accounts described by `config`
+    pub async fn start(config: FlashLoanFixtureConfig) -> Self {
+        let program_id = config.program_id;
+        let receiver_program_id = Pubkey::new_unique();
+
+        let mut program_test = ProgramTest::default();
+        // Native SPL Token keeps the mock independent from the BPF VM
+        program_test.add_program(
+            "spl_token",
+            spl_token::id(),
+            processor!(spl_token::processor::Processor::process),
+        );
+        program_test.add_program(
+            "spl_token_2022",
+            spl_token_2022::id(),
+            processor!(spl_token_2022::processor::Processor::process),
+        );
+        program_test.add_program(
+            "spl_associated_token_account",
+            spl_associated_token_account::id(),
+            processor!(spl_associated_token_account::processor::process_instruction),
+        );
+        match &config.program_so {
+            Some(path) => {
+                let data = std::fs::read(path).expect("Reading Flash Loan program");
+                program_test.add_account(
+                    program_id,
+                    Account {
+                        lamports: Rent::default().minimum_balance(data.len()),
+                        data,
+                        owner: bpf_loader::id(),
+                        executable: true,
+                        rent_epoch: 0,
+                    },
+                );
+            }
+            None => program_test.add_program(
+                "flash_loan_mock",
+                program_id,
+                processor!(mock_program::process_instruction),
+            ),
+        }
+        program_test.add_program(
+            "flash_loan_receiver_mock",
+            receiver_program_id,
+            processor!(mock_receiver::process_instruction),
+        );
+
+        let lending_market = Pubkey::new_unique();
+        let (lending_market_authority, _bump_seed) = Pubkey::find_program_address(
+            &[&lending_market.to_bytes()[..PUBKEY_BYTES]],
+            &program_id,
+        );
+        let reserve_pubkey = Pubkey::new_unique();
+        let (mint, mint_decimals) = if config.native_mint {
+            (native_mint::id(), native_mint::DECIMALS)
+        } else {
+            (Pubkey::new_unique(), config.mint_decimals)
+        };
+        let supply = Pubkey::new_unique();
+        let fee_receiver = Pubkey::new_unique();
+        let user = Keypair::new();
+        let user_liquidity = Pubkey::new_unique();
+
+        add_data_account(
+            &mut program_test,
+            lending_market,
+            config.lending_market_data.clone(),
+            program_id,
+        );
+        let mint_supply = config.reserve_liquidity + config.user_liquidity;
+        let token_program = match config.transfer_fee_bps {
+            Some(fee_bps) if !config.native_mint => {
+                let mut data = mint_with_transfer_fee(mint_decimals, fee_bps, u64::MAX);
+                let mut state =
+                    StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack(&mut data)
+                        .expect("Unpacking mint");
+                state.base.supply = mint_supply;
+                state.pack_base();
+                add_data_account(&mut program_test, mint, data, spl_token_2022::id());
+                spl_token_2022::id()
+            }
+            _ => {
+                add_packable_account(
+                    &mut program_test,
+                    mint,
+                    Mint {
+                        mint_authority: COption::None,
+                        supply: mint_supply,
+                        decimals: mint_decimals,
+                        is_initialized: true,
+                        freeze_authority: COption::None,
+                    },
+                    spl_token::id(),
+                );
+                spl_token::id()
+            }
+        };
+        for (address, owner, amount) in [
+            (supply, lending_market_authority, config.reserve_liquidity),
+            (fee_receiver, Pubkey::new_unique(), 0),
+            (user_liquidity, user.pubkey(), config.user_liquidity),
+        ]
+        .iter()
+        {
+            add_token_account(
+                &mut program_test,
+                *address,
+                mint,
+                *owner,
+                *amount,
+                token_program,
+            );
+        }
+
+        let reserve = ReserveBuilder::new()
+            .lending_market(lending_market)
+            .mint(mint)
+            .decimals(mint_decimals)
+            .supply(supply)
+            .available_liquidity(config.reserve_liquidity)
+            .fee_bps(config.fee_bps)
+            .texture_fee_percentage(config.texture_fee_percentage)
+            .deposit_limit(config.deposit_limit)
+            .fee_receiver(fee_receiver);
+        add_data_account(
+            &mut program_test,
+            reserve_pubkey,
+            reserve.pack(),
+            program_id,
+        );
+
+        let context = program_test.start_with_context().await;
+
+        Self {
+            context,
+            program_id,
+            receiver_program_id,
+            lending_market,
+            lending_market_authority,
+            reserve_pubkey,
+            mint,
+            token_program,
+            supply,
+            fee_receiver,
+            user,
+            user_liquidity,
+        }
+    }
+
+    /// Reads the current state of the reserve
+    pub async fn reserve(&mut self) -> Reserve {
+        let account = self
+            .context
+            .banks_client
+            .get_account(self.reserve_pubkey)
+            .await
+            .expect("Getting reserve account")
+            .expect("Reserve account exists");
+        Reserve::unpack(&account.data).expect("Unpacking reserve")
+    }
+
+    /// Reads the balance of a token account
+    pub async fn token_balance(&mut self, token_account: Pubkey) -> u64 {
+        let account = self
+            .context
+            .banks_client
+            .get_account(token_account)
+            .await
+            .expect("Getting token account")
+            .expect("Token account exists");
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+            .expect("Unpacking token account")
+            .base
+            .amount
+    }
+
+    /// Creates a `FlashBorrow` instruction sending `amount` to the user's liquidity account
+    pub fn flash_borrow(&self, amount: u64) -> Instruction {
+        flash_borrow_with_token_program(
+            self.program_id,
+            amount,
+            self.supply,
+            self.user_liquidity,
+            self.reserve_pubkey,
+            self.lending_market,
+            self.token_program,
+            Some(self.mint),
+        )
+    }
+
+    /// Creates a `FlashRepay` instruction repaying `amount` from the user's liquidity account
+    pub fn flash_repay(&self, amount: u64) -> Instruction {
+        flash_repay_with_token_program(
+            self.program_id,
+            amount,
+            self.user_liquidity,
+            self.supply,
+            self.fee_receiver,
+            self.reserve_pubkey,
+            self.lending_market,
+            self.user.pubkey(),
+            self.token_program,
+            Some(self.mint),
+        )
+    }
+
+    /// Processes `instructions` in one transaction paid by `context.payer` and signed by the user
+    /// when required
+    pub async fn process_transaction(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<(), BanksClientError> {
+        let transaction = self.sign_transaction(instructions).await?;
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    /// Simulates `instructions` in one transaction signed like in
+    /// [process_transaction](#method.process_transaction) and returns the program logs.
+    /// A failed simulation is returned as `BanksClientError::SimulationError`.
+    pub async fn simulate_transaction(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<Vec<String>, BanksClientError> {
+        let transaction = self.sign_transaction(instructions).await?;
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await?;
+        let details = simulation
+            .simulation_details
+            .ok_or(BanksClientError::ClientError("No simulation details"))?;
+        match simulation.result {
+            Some(Err(err)) => Err(BanksClientError::SimulationError {
+                err,
+                logs: details.logs,
+                units_consumed: details.units_consumed,
+                return_data: details.return_data,
+            }),
+            _ => Ok(details.logs),
+        }
+    }
+
+    async fn sign_transaction(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<Transaction, BanksClientError> {
+        let blockhash = self.context.banks_client.get_latest_blockhash().await?;
+        let mut transaction =
+            Transaction::new_with_payer(instructions, Some(&self.context.payer.pubkey()));
+        let signer_keys = transaction.message.signer_keys();
+        let signers = [&self.context.payer, &self.user]
+            .iter()
+            .copied()
+            .filter(|signer| signer_keys.contains(&&signer.pubkey()))
+            .collect::<Vec<_>>();
+        transaction.sign(&signers, blockhash);
+        Ok(transaction)
+    }
+}
+
+fn add_data_account(program_test: &mut ProgramTest, address: Pubkey, data: Vec<u8>, owner: Pubkey) {
+    program_test.add_account(
+        address,
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+fn add_packable_account<T: Pack>(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    state: T,
+    owner: Pubkey,
+) {
+    let mut data = vec![0; T::LEN];
+    state.pack_into_slice(&mut data);
+    add_data_account(program_test, address, data, owner);
+}
+
+fn add_token_account(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    mint: Pubkey,
+    owner: Pubkey,
+    amount: u64,
+    token_program: Pubkey,
+) {
+    let rent_exempt_reserve = Rent::default().minimum_balance(TokenAccount::LEN);
+    let is_native = mint == native_mint::id();
+    let account = TokenAccount {
+        mint,
+        owner,
+        amount,
+        state: AccountState::Initialized,
+        is_native: if is_native {
+            COption::Some(rent_exempt_reserve)
+        } else {
+            COption::None
+        },
+        ..TokenAccount::default()
+    };
+    let data = if token_program == spl_token_2022::id() {
+        // Accounts of a mint with a transfer fee hold the withheld amount
+        let len = ExtensionType::get_account_len::<spl_token_2022::state::Account>(&[
+            ExtensionType::TransferFeeAmount,
+        ]);
+        let mut data = vec![0; len];
+        let mut state =
+            StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack_uninitialized(
+                &mut data,
+            )
+            .expect("Unpacking uninitialized token account");
+        state
+            .init_extension::<TransferFeeAmount>(true)
+            .expect("Initializing transfer fee amount extension");
+        state.base = spl_token_2022::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token_2022::state::AccountState::Initialized,
+            ..spl_token_2022::state::Account::default()
+        };
+        state.pack_base();
+        state
+            .init_account_type()
+            .expect("Initializing token account type");
+        data
+    } else {
+        let mut data = vec![0; TokenAccount::LEN];
+        account.pack_into_slice(&mut data);
+        data
+    };
+
+    program_test.add_account(
+        address,
+        Account {
+            lamports: if is_native {
+                rent_exempt_reserve + amount
+            } else {
+                Rent::default().minimum_balance(data.len())
+            },
+            data,
+            owner: token_program,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::clock::Clock;
+    use solana_program::instruction::InstructionError;
+    use solana_program::program_error::ProgramError;
+    use solana_sdk::transaction::TransactionError;
+
+    use super::*;
+    use crate::error::FlashProgramError;
+    use crate::flash_loan_fee;
+    use crate::instruction::{flash_loan, flash_loan_with_token_program};
+    use crate::quote::FlashLoanQuote;
+
+    fn custom_error(index: u8, error: FlashProgramError) -> TransactionError {
+        let code = match ProgramError::from(error) {
+            ProgramError::Custom(code) => code,
+            _ => unreachable!(),
+        };
+        TransactionError::InstructionError(index, InstructionError::Custom(code))
+    }
+
+    #[tokio::test]
+    async fn flash_borrow_and_repay() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let reserve = fixture.reserve().await;
+        let amount = 5_000_000_000;
+        let fee = flash_loan_fee(&reserve, amount).unwrap();
+        assert_eq!(fee, 15_000_000);
+
+        let instructions = [fixture.flash_borrow(amount), fixture.flash_repay(amount)];
+        fixture.process_transaction(&instructions).await.unwrap();
+
+        assert_eq!(fixture.token_balance(fixture.fee_receiver).await, 3_000_000);
+        assert_eq!(
+            fixture.token_balance(fixture.supply).await,
+            1_000_000_000_000 + 12_000_000
+        );
+        assert_eq!(
+            fixture.token_balance(fixture.user_liquidity).await,
+            10_000_000_000 - fee
+        );
+        assert_eq!(
+            fixture.reserve().await.liquidity.available_amount,
+            1_000_000_000_000 + 12_000_000
+        );
+    }
+
+    #[tokio::test]
+    async fn flash_borrow_and_repay_with_transfer_fee() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig {
+            transfer_fee_bps: Some(100),
+            ..FlashLoanFixtureConfig::default()
+        })
+        .await;
+        assert_eq!(fixture.token_program, spl_token_2022::id());
+        let reserve = fixture.reserve().await;
+        let mint = fixture
+            .context
+            .banks_client
+            .get_account(fixture.mint)
+            .await
+            .unwrap()
+            .unwrap();
+        let clock = fixture
+            .context
+            .banks_client
+            .get_sysvar::<Clock>()
+            .await
+            .unwrap();
+        let amount = 5_000_000_000;
+        let quote =
+            FlashLoanQuote::with_transfer_fee(&reserve, amount, &mint.data, clock.epoch).unwrap();
+        assert_eq!(quote.fee, 15_000_000);
+        assert_eq!(quote.borrow_transfer_fee, 50_000_000);
+
+        let instructions = [fixture.flash_borrow(amount), fixture.flash_repay(amount)];
+        fixture.process_transaction(&instructions).await.unwrap();
+
+        assert_eq!(
+            fixture.token_balance(fixture.user_liquidity).await,
+            10_000_000_000 - quote.top_up_amount
+        );
+        // 1% is withheld from the 3_000_000 texture fee and the 5_012_000_000 repaid to the pool
+        assert_eq!(fixture.token_balance(fixture.fee_receiver).await, 2_970_000);
+        assert_eq!(
+            fixture.token_balance(fixture.supply).await,
+            1_000_000_000_000 - 5_000_000_000 + 5_012_000_000 - 50_120_000
+        );
+        // Only what reaches the supply account is credited
+        assert_eq!(
+            fixture.reserve().await.liquidity.available_amount,
+            fixture.token_balance(fixture.supply).await
+        );
+    }
+
+    #[tokio::test]
+    async fn flash_borrow_with_invalid_mint() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let mut borrow = fixture.flash_borrow(1_000_000);
+        borrow.accounts[7].pubkey = fixture.user_liquidity;
+        let instructions = [borrow, fixture.flash_repay(1_000_000)];
+
+        let err = fixture
+            .process_transaction(&instructions)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            custom_error(0, FlashProgramError::InvalidAccountInput)
+        );
+    }
+
+    #[tokio::test]
+    async fn flash_borrow_and_repay_without_mint() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let mut borrow = fixture.flash_borrow(1_000_000_000);
+        let mut repay = fixture.flash_repay(1_000_000_000);
+        borrow.accounts.pop();
+        repay.accounts.pop();
+        fixture.process_transaction(&[borrow, repay]).await.unwrap();
+
+        assert_eq!(fixture.token_balance(fixture.fee_receiver).await, 600_000);
+    }
+
+    #[tokio::test]
+    async fn cpi_flash_loan_with_transfer_fee() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig {
+            transfer_fee_bps: Some(100),
+            ..FlashLoanFixtureConfig::default()
+        })
+        .await;
+        let instruction = flash_loan_with_token_program(
+            fixture.program_id,
+            1_000_000_000,
+            0,
+            fixture.supply,
+            fixture.user_liquidity,
+            fixture.reserve_pubkey,
+            fixture.fee_receiver,
+            fixture.lending_market,
+            fixture.receiver_program_id,
+            vec![solana_program::instruction::AccountMeta::new_readonly(
+                fixture.user.pubkey(),
+                true,
+            )],
+            fixture.token_program,
+        );
+
+        // `FlashLoan` has no mint account to transfer with `transfer_checked`
+        let err = fixture
+            .process_transaction(&[instruction])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            custom_error(0, FlashProgramError::TokenTransferFailed)
+        );
+    }
+
+    #[tokio::test]
+    async fn flash_borrow_without_repay() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let instructions = [fixture.flash_borrow(1_000_000)];
+
+        let err = fixture
+            .process_transaction(&instructions)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            custom_error(0, FlashProgramError::NoFlashRepayFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn flash_repay_amount_mismatch() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let instructions = [
+            fixture.flash_borrow(1_000_000),
+            fixture.flash_repay(999_999),
+        ];
+
+        let err = fixture
+            .process_transaction(&instructions)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            custom_error(0, FlashProgramError::InvalidFlashRepay)
+        );
+    }
+
+    #[tokio::test]
+    async fn flash_borrow_too_small() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let instructions = [fixture.flash_borrow(2), fixture.flash_repay(2)];
+
+        let err = fixture
+            .process_transaction(&instructions)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            custom_error(0, FlashProgramError::BorrowTooSmall)
+        );
+    }
+
+    #[tokio::test]
+    async fn cpi_flash_loan() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let amount = 1_000_000_000;
+        let instruction = flash_loan(
+            fixture.program_id,
+            amount,
+            0,
+            fixture.supply,
+            fixture.user_liquidity,
+            fixture.reserve_pubkey,
+            fixture.fee_receiver,
+            fixture.lending_market,
+            fixture.receiver_program_id,
+            vec![solana_program::instruction::AccountMeta::new_readonly(
+                fixture.user.pubkey(),
+                true,
+            )],
+        );
+        fixture.process_transaction(&[instruction]).await.unwrap();
+
+        assert_eq!(fixture.token_balance(fixture.fee_receiver).await, 600_000);
+        assert_eq!(
+            fixture.token_balance(fixture.user_liquidity).await,
+            10_000_000_000 - 3_000_000
+        );
+        assert_eq!(
+            fixture.reserve().await.liquidity.available_amount,
+            1_000_000_000_000 + 2_400_000
+        );
+    }
+}
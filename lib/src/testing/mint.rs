@@ -0,0 +1,42 @@
+//! Token mint fixtures
+
+use solana_program::program_option::COption;
+use spl_token_2022::extension::transfer_fee::{TransferFee, TransferFeeConfig};
+use spl_token_2022::extension::{ExtensionType, StateWithExtensionsMut};
+use spl_token_2022::pod::OptionalNonZeroPubkey;
+use spl_token_2022::state::Mint;
+
+/// Returns data of an initialized Token-2022 mint charging `fee_bps` on transfers, capped by
+/// `maximum_fee`
+pub fn mint_with_transfer_fee(decimals: u8, fee_bps: u16, maximum_fee: u64) -> Vec<u8> {
+    let len = ExtensionType::get_account_len::<Mint>(&[ExtensionType::TransferFeeConfig]);
+    let mut data = vec![0; len];
+    let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data)
+        .expect("Unpacking uninitialized mint");
+
+    let transfer_fee = TransferFee {
+        epoch: 0.into(),
+        maximum_fee: maximum_fee.into(),
+        transfer_fee_basis_points: fee_bps.into(),
+    };
+    let extension = state
+        .init_extension::<TransferFeeConfig>(true)
+        .expect("Initializing transfer fee extension");
+    extension.transfer_fee_config_authority = OptionalNonZeroPubkey::default();
+    extension.withdraw_withheld_authority = OptionalNonZeroPubkey::default();
+    extension.older_transfer_fee = transfer_fee;
+    extension.newer_transfer_fee = transfer_fee;
+
+    state.base = Mint {
+        mint_authority: COption::None,
+        supply: 0,
+        decimals,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    };
+    state.pack_base();
+    state
+        .init_account_type()
+        .expect("Initializing mint account type");
+    data
+}
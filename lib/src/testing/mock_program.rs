@@ -0,0 +1,569 @@
+//! Rust mock of the Flash Loan program.
+//!
+//! Implements `FlashLoan`, `FlashBorrow` and `FlashRepay` with the same account layouts as
+//! described in [FlashLoanInstruction](../../instruction/enum.FlashLoanInstruction.html), returns
+//! the same [FlashProgramError](../../error/enum.FlashProgramError.html) codes and charges fees
+//! with the program's formula in plain integer math, independent of the SDK's
+//! [flash_loan_fee](../../fn.flash_loan_fee.html) it is used to test. Lending market accounts are
+//! only checked for ownership, their content is not interpreted.
+//!
+//! Both SPL Token and Token-2022 are accepted. `FlashBorrow` and `FlashRepay` move liquidity with
+//! `transfer_checked` when the optional reserve liquidity mint follows the token program and with
+//! `transfer` otherwise. Token-2022 mints with a transfer fee need the former. `FlashLoan` has no
+//! mint account, its trailing accounts belong to the receiver program, so it always uses
+//! `transfer` and fails with `TokenTransferFailed` for such mints.
+//!
+//! `FlashRepay` credits the reserve with what the supply account actually receives, i.e. the
+//! repaid amount and the pool's share of the fee minus any transfer fee withheld by Token-2022.
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::{Pubkey, PUBKEY_BYTES},
+    sysvar::{self, instructions, Sysvar},
+};
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::Account as TokenAccount;
+
+use crate::error::FlashProgramError;
+use crate::instruction::FlashLoanInstruction;
+use crate::types::Reserve;
+
+/// Scale of `flash_loan_fee_wad`
+const WAD: u128 = 1_000_000_000_000_000_000;
+
+/// Processes an instruction of the mock Flash Loan program.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    match FlashLoanInstruction::unpack(input)? {
+        FlashLoanInstruction::FlashLoan {
+            amount,
+            receive_flash_loan_instruction_tag,
+        } => {
+            msg!("Instruction: Flash Loan");
+            process_flash_loan(
+                program_id,
+                amount,
+                receive_flash_loan_instruction_tag,
+                accounts,
+            )
+        }
+        FlashLoanInstruction::FlashBorrow { amount } => {
+            msg!("Instruction: Flash Borrow");
+            process_flash_borrow(program_id, amount, accounts)
+        }
+        FlashLoanInstruction::FlashRepay { amount } => {
+            msg!("Instruction: Flash Repay");
+            process_flash_repay(program_id, amount, accounts)
+        }
+    }
+}
+
+fn process_flash_loan(
+    program_id: &Pubkey,
+    amount: u64,
+    receive_flash_loan_instruction_tag: u8,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let fee_receiver_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let receiver_program_info = next_account_info(account_info_iter)?;
+    let receiver_accounts = account_info_iter.as_slice();
+
+    let mut reserve = load_reserve(program_id, reserve_info, lending_market_info)?;
+    let bump_seed = check_market_authority(
+        program_id,
+        lending_market_info,
+        lending_market_authority_info,
+    )?;
+    check_supply(&reserve, source_liquidity_info)?;
+    check_fee_receiver(&reserve, fee_receiver_info)?;
+    check_token_program(token_program_info)?;
+    if receiver_program_info.key == program_id {
+        msg!("Flash loan receiver program cannot be the flash loan program");
+        return Err(FlashProgramError::InvalidFlashLoanReceiverProgram.into());
+    }
+
+    let amount = if amount == u64::MAX {
+        reserve.liquidity.available_amount
+    } else {
+        amount
+    };
+    let fee = borrow_fee(&reserve, amount)?;
+    check_available_liquidity(&reserve, amount)?;
+    let texture_fee = texture_fee(&reserve, fee)?;
+
+    let balance_before = token_amount(source_liquidity_info)?;
+    transfer(
+        source_liquidity_info,
+        destination_liquidity_info,
+        lending_market_authority_info,
+        token_program_info,
+        None,
+        &reserve,
+        amount,
+        &[
+            &lending_market_info.key.to_bytes()[..PUBKEY_BYTES],
+            &[bump_seed],
+        ],
+    )?;
+
+    let repay_amount = amount
+        .checked_add(fee)
+        .ok_or(FlashProgramError::MathOverflow)?;
+    let mut data = Vec::with_capacity(9);
+    data.push(receive_flash_loan_instruction_tag);
+    data.extend_from_slice(&repay_amount.to_le_bytes());
+
+    let mut receiver_metas = vec![
+        AccountMeta::new(*destination_liquidity_info.key, false),
+        AccountMeta::new(*source_liquidity_info.key, false),
+        AccountMeta::new_readonly(*token_program_info.key, false),
+    ];
+    receiver_metas.extend(receiver_accounts.iter().map(|info| AccountMeta {
+        pubkey: *info.key,
+        is_signer: info.is_signer,
+        is_writable: info.is_writable,
+    }));
+    invoke(
+        &Instruction {
+            program_id: *receiver_program_info.key,
+            accounts: receiver_metas,
+            data,
+        },
+        accounts,
+    )?;
+
+    let balance_after = token_amount(source_liquidity_info)?;
+    let required_balance = balance_before
+        .checked_add(fee)
+        .ok_or(FlashProgramError::MathOverflow)?;
+    if balance_after < required_balance {
+        msg!("Insufficient reserve liquidity after flash loan");
+        return Err(FlashProgramError::NotEnoughLiquidityAfterFlashLoan.into());
+    }
+
+    if texture_fee > 0 {
+        transfer(
+            source_liquidity_info,
+            fee_receiver_info,
+            lending_market_authority_info,
+            token_program_info,
+            None,
+            &reserve,
+            texture_fee,
+            &[
+                &lending_market_info.key.to_bytes()[..PUBKEY_BYTES],
+                &[bump_seed],
+            ],
+        )?;
+    }
+
+    reserve.liquidity.available_amount = reserve
+        .liquidity
+        .available_amount
+        .checked_add(fee - texture_fee)
+        .ok_or(FlashProgramError::MathOverflow)?;
+    reserve.last_update = Clock::get()?.slot;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    msg!(
+        "Flash loan {} from reserve {}, fee {}",
+        amount,
+        reserve_info.key,
+        fee
+    );
+    Ok(())
+}
+
+fn process_flash_borrow(
+    program_id: &Pubkey,
+    amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let sysvar_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut reserve = load_reserve(program_id, reserve_info, lending_market_info)?;
+    let mint_info = next_mint_info(&reserve, account_info_iter)?;
+    let bump_seed = check_market_authority(
+        program_id,
+        lending_market_info,
+        lending_market_authority_info,
+    )?;
+    check_supply(&reserve, source_liquidity_info)?;
+    check_token_program(token_program_info)?;
+    if sysvar_info.key != &sysvar::instructions::id() {
+        msg!("Invalid instructions sysvar");
+        return Err(FlashProgramError::InvalidAccountInput.into());
+    }
+
+    // Fee is computed here only to fail early on `BorrowTooSmall`
+    borrow_fee(&reserve, amount)?;
+    check_available_liquidity(&reserve, amount)?;
+
+    let current_index = instructions::load_current_index_checked(sysvar_info)? as usize;
+    let current_ixn = instructions::load_instruction_at_checked(current_index, sysvar_info)?;
+    if current_ixn.program_id != *program_id {
+        msg!("Flash Borrow was called via CPI!");
+        return Err(FlashProgramError::FlashBorrowCpi.into());
+    }
+
+    let mut found_repay = false;
+    let mut index = current_index + 1;
+    while let Ok(ixn) = instructions::load_instruction_at_checked(index, sysvar_info) {
+        index += 1;
+        if ixn.program_id != *program_id {
+            continue;
+        }
+        match FlashLoanInstruction::unpack(&ixn.data) {
+            Ok(FlashLoanInstruction::FlashRepay {
+                amount: repay_amount,
+            }) => {
+                if ixn.accounts.get(3).map(|meta| meta.pubkey) != Some(*reserve_info.key) {
+                    continue;
+                }
+                if repay_amount != amount {
+                    msg!("Invalid repay amount");
+                    return Err(FlashProgramError::InvalidFlashRepay.into());
+                }
+                found_repay = true;
+                break;
+            }
+            Ok(FlashLoanInstruction::FlashBorrow { .. }) => {
+                msg!("Multiple flash borrows not allowed");
+                return Err(FlashProgramError::MultipleFlashBorrows.into());
+            }
+            _ => {}
+        }
+    }
+    if !found_repay {
+        msg!("No flash repay found");
+        return Err(FlashProgramError::NoFlashRepayFound.into());
+    }
+
+    transfer(
+        source_liquidity_info,
+        destination_liquidity_info,
+        lending_market_authority_info,
+        token_program_info,
+        mint_info,
+        &reserve,
+        amount,
+        &[
+            &lending_market_info.key.to_bytes()[..PUBKEY_BYTES],
+            &[bump_seed],
+        ],
+    )?;
+
+    reserve.liquidity.available_amount -= amount;
+    reserve.last_update = Clock::get()?.slot;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    msg!("Flash borrow {} from reserve {}", amount, reserve_info.key);
+    Ok(())
+}
+
+fn process_flash_repay(
+    program_id: &Pubkey,
+    amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let fee_receiver_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let sysvar_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut reserve = load_reserve(program_id, reserve_info, lending_market_info)?;
+    let mint_info = next_mint_info(&reserve, account_info_iter)?;
+    check_supply(&reserve, destination_liquidity_info)?;
+    check_fee_receiver(&reserve, fee_receiver_info)?;
+    check_token_program(token_program_info)?;
+    if !user_transfer_authority_info.is_signer {
+        msg!("User transfer authority must be a signer");
+        return Err(FlashProgramError::InvalidSigner.into());
+    }
+    if sysvar_info.key != &sysvar::instructions::id() {
+        msg!("Invalid instructions sysvar");
+        return Err(FlashProgramError::InvalidAccountInput.into());
+    }
+
+    let fee = borrow_fee(&reserve, amount)?;
+    let texture_fee = texture_fee(&reserve, fee)?;
+
+    let current_index = instructions::load_current_index_checked(sysvar_info)? as usize;
+    let current_ixn = instructions::load_instruction_at_checked(current_index, sysvar_info)?;
+    if current_ixn.program_id != *program_id {
+        msg!("Flash Repay was called via CPI!");
+        return Err(FlashProgramError::FlashRepayCpi.into());
+    }
+
+    let borrow = (0..current_index).rev().find_map(|index| {
+        let ixn = instructions::load_instruction_at_checked(index, sysvar_info).ok()?;
+        if ixn.program_id != *program_id
+            || ixn.accounts.get(2).map(|meta| meta.pubkey) != Some(*reserve_info.key)
+        {
+            return None;
+        }
+        match FlashLoanInstruction::unpack(&ixn.data) {
+            Ok(FlashLoanInstruction::FlashBorrow { amount }) => Some(amount),
+            _ => None,
+        }
+    });
+    if borrow != Some(amount) {
+        msg!("No matching flash borrow found");
+        return Err(FlashProgramError::InvalidFlashRepay.into());
+    }
+
+    let pool_fee = fee - texture_fee;
+    let pool_repay_amount = amount
+        .checked_add(pool_fee)
+        .ok_or(FlashProgramError::MathOverflow)?;
+    let supply_before = token_amount(destination_liquidity_info)?;
+    transfer(
+        source_liquidity_info,
+        destination_liquidity_info,
+        user_transfer_authority_info,
+        token_program_info,
+        mint_info,
+        &reserve,
+        pool_repay_amount,
+        &[],
+    )?;
+    if texture_fee > 0 {
+        transfer(
+            source_liquidity_info,
+            fee_receiver_info,
+            user_transfer_authority_info,
+            token_program_info,
+            mint_info,
+            &reserve,
+            texture_fee,
+            &[],
+        )?;
+    }
+
+    // Token-2022 may withhold a transfer fee from the repaid liquidity
+    let received = token_amount(destination_liquidity_info)?
+        .checked_sub(supply_before)
+        .ok_or(FlashProgramError::MathOverflow)?;
+    reserve.liquidity.available_amount = reserve
+        .liquidity
+        .available_amount
+        .checked_add(received)
+        .ok_or(FlashProgramError::MathOverflow)?;
+    reserve.last_update = Clock::get()?.slot;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    msg!("Flash repay {} with fee {}", amount, fee);
+    Ok(())
+}
+
+fn load_reserve(
+    program_id: &Pubkey,
+    reserve_info: &AccountInfo,
+    lending_market_info: &AccountInfo,
+) -> Result<Reserve, ProgramError> {
+    if reserve_info.owner != program_id || lending_market_info.owner != program_id {
+        msg!("Reserve and lending market must be owned by the flash loan program");
+        return Err(FlashProgramError::InvalidAccountOwner.into());
+    }
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(FlashProgramError::InvalidAccountInput.into());
+    }
+    Ok(reserve)
+}
+
+fn check_market_authority(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    lending_market_authority_info: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (authority, bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_info.key.to_bytes()[..PUBKEY_BYTES]],
+        program_id,
+    );
+    if &authority != lending_market_authority_info.key {
+        msg!(
+            "Derived lending market authority does not match the lending market authority provided"
+        );
+        return Err(FlashProgramError::InvalidMarketAuthority.into());
+    }
+    Ok(bump_seed)
+}
+
+fn check_supply(reserve: &Reserve, supply_info: &AccountInfo) -> ProgramResult {
+    if &reserve.liquidity.supply_pubkey != supply_info.key {
+        msg!("Reserve liquidity supply does not match the reserve liquidity supply provided");
+        return Err(FlashProgramError::InvalidAccountInput.into());
+    }
+    Ok(())
+}
+
+fn check_fee_receiver(reserve: &Reserve, fee_receiver_info: &AccountInfo) -> ProgramResult {
+    if &reserve.config.fee_receiver != fee_receiver_info.key {
+        msg!("Reserve fee receiver does not match the fee receiver provided");
+        return Err(FlashProgramError::InvalidAccountInput.into());
+    }
+    Ok(())
+}
+
+fn check_token_program(token_program_info: &AccountInfo) -> ProgramResult {
+    if token_program_info.key != &spl_token::id() && token_program_info.key != &spl_token_2022::id()
+    {
+        msg!("Token program is neither SPL Token nor Token-2022");
+        return Err(FlashProgramError::InvalidTokenProgram.into());
+    }
+    Ok(())
+}
+
+fn check_available_liquidity(reserve: &Reserve, amount: u64) -> ProgramResult {
+    if amount > reserve.liquidity.available_amount {
+        msg!("Flash borrow amount cannot exceed available liquidity");
+        return Err(FlashProgramError::BorrowTooLarge.into());
+    }
+    Ok(())
+}
+
+/// Fee of borrowing `amount`. The repay computes it again after the borrow took `amount` out of the
+/// available liquidity, so the liquidity is checked separately.
+fn borrow_fee(reserve: &Reserve, amount: u64) -> Result<u64, ProgramError> {
+    if reserve.config.fees.flash_loan_fee_wad == u64::MAX {
+        msg!("Flash loans are disabled for this reserve");
+        return Err(FlashProgramError::FlashLoansDisabled.into());
+    }
+    if amount == 0 {
+        msg!("Flash borrow amount must be greater than zero");
+        return Err(FlashProgramError::InvalidAmount.into());
+    }
+    let fee_wad = reserve.config.fees.flash_loan_fee_wad as u128;
+    if fee_wad == 0 {
+        return Ok(0);
+    }
+    let minimum_fee = if reserve.config.fees.texture_fee_percentage > 0 {
+        2
+    } else {
+        1
+    };
+    // Unrounded fee scaled by WAD
+    let scaled_fee = (amount as u128)
+        .checked_mul(fee_wad)
+        .ok_or(FlashProgramError::MathOverflow)?
+        .max(minimum_fee * WAD);
+    if scaled_fee >= amount as u128 * WAD {
+        msg!("Flash borrow amount is too small to pay fees");
+        return Err(FlashProgramError::BorrowTooSmall.into());
+    }
+    // Rounded half up
+    Ok(((scaled_fee + WAD / 2) / WAD) as u64)
+}
+
+/// Part of `fee` going to the reserve fee receiver, the rest stays in the liquidity pool.
+/// Rounded half up like the flash loan fee.
+fn texture_fee(reserve: &Reserve, fee: u64) -> Result<u64, ProgramError> {
+    let percentage = reserve.config.fees.texture_fee_percentage as u128;
+    if percentage > 0 && fee > 0 {
+        let texture_fee = ((fee as u128 * percentage + 50) / 100).max(1) as u64;
+        Ok(texture_fee.min(fee))
+    } else {
+        Ok(0)
+    }
+}
+
+fn next_mint_info<'a, 'b, I: Iterator<Item = &'b AccountInfo<'a>>>(
+    reserve: &Reserve,
+    account_info_iter: &mut I,
+) -> Result<Option<&'b AccountInfo<'a>>, ProgramError> {
+    match account_info_iter.next() {
+        Some(mint_info) if mint_info.key != &reserve.liquidity.mint_pubkey => {
+            msg!("Invalid reserve liquidity mint");
+            Err(FlashProgramError::InvalidAccountInput.into())
+        }
+        mint_info => Ok(mint_info),
+    }
+}
+
+fn token_amount(token_account_info: &AccountInfo) -> Result<u64, ProgramError> {
+    let data = token_account_info.data.borrow();
+    let account = StateWithExtensions::<TokenAccount>::unpack(&data)
+        .map_err(|_| FlashProgramError::InvalidTokenAccount)?;
+    Ok(account.base.amount)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn transfer<'a>(
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    mint: Option<&AccountInfo<'a>>,
+    reserve: &Reserve,
+    amount: u64,
+    authority_signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let mut account_infos = vec![
+        source.clone(),
+        destination.clone(),
+        authority.clone(),
+        token_program.clone(),
+    ];
+    let ix = match mint {
+        Some(mint) => {
+            account_infos.push(mint.clone());
+            spl_token_2022::instruction::transfer_checked(
+                token_program.key,
+                source.key,
+                mint.key,
+                destination.key,
+                authority.key,
+                &[],
+                amount,
+                reserve.liquidity.mint_decimals as u8,
+            )?
+        }
+        // Rejected by Token-2022 for mints with a transfer fee
+        #[allow(deprecated)]
+        None => spl_token_2022::instruction::transfer(
+            token_program.key,
+            source.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount,
+        )?,
+    };
+    if authority_signer_seeds.is_empty() {
+        invoke(&ix, &account_infos)
+    } else {
+        invoke_signed(&ix, &account_infos, &[authority_signer_seeds])
+    }
+    .map_err(|_| FlashProgramError::TokenTransferFailed.into())
+}
@@ -0,0 +1,64 @@
+//! Minimal flash loan receiver program for testing the CPI style `FlashLoan` instruction.
+//!
+//! Implements `ReceiveFlashLoan` with tag `0` and returns the requested amount straight back.
+//!
+//! Accounts expected by `ReceiveFlashLoan`:
+//!
+//!   0. `[writable]` Source liquidity (borrowed tokens were sent here).
+//!   1. `[writable]` Destination liquidity (reserve liquidity supply).
+//!   2. `[]` Token program id.
+//!   3. `[signer]` Source liquidity owner.
+
+use std::convert::TryInto;
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Processes an instruction of the mock flash loan receiver program.
+pub fn process_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    let (&tag, rest) = input
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    if tag != 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let amount = rest
+        .get(..8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+
+    msg!("Receive flash loan, repaying {}", amount);
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program_info.key,
+            source_liquidity_info.key,
+            destination_liquidity_info.key,
+            owner_info.key,
+            &[],
+            amount,
+        )?,
+        &[
+            source_liquidity_info.clone(),
+            destination_liquidity_info.clone(),
+            owner_info.clone(),
+            token_program_info.clone(),
+        ],
+    )
+}
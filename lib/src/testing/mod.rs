@@ -0,0 +1,18 @@
+//! Local test harness for code built on top of this SDK.
+//!
+//! Enabled by the `testing` feature. [FlashLoanFixture](fixture/struct.FlashLoanFixture.html)
+//! boots `solana-program-test` with either the real Flash Loan program `.so` or the Rust mock
+//! from [mock_program](mock_program/index.html), and pre-creates a lending market, a funded reserve
+//! and a funded user token account. No cluster access is needed.
+//! [ReserveBuilder](reserve_builder/struct.ReserveBuilder.html) builds standalone reserves for
+//! unit tests.
+
+pub mod fixture;
+pub mod mint;
+pub mod mock_program;
+pub mod mock_receiver;
+pub mod reserve_builder;
+
+pub use fixture::*;
+pub use mint::*;
+pub use reserve_builder::*;
@@ -0,0 +1,172 @@
+//! Builder of `Reserve` fixtures from human readable values
+
+use solana_program::clock::Slot;
+use solana_program::program_pack::Pack;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_sdk::account::Account;
+
+use crate::math::Rate;
+use crate::types::{Reserve, RESERVE_VERSION};
+use crate::FLASH_LOAN_ID;
+
+/// Builds [Reserve](../../types/struct.Reserve.html) values for tests.
+///
+/// Addresses default to unique pubkeys, the fee defaults to 0.3% with 20% going to Texture and
+/// the deposit limit is unlimited.
+#[derive(Clone, Debug)]
+pub struct ReserveBuilder {
+    reserve: Reserve,
+}
+
+impl Default for ReserveBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReserveBuilder {
+    /// Creates a builder of an initialized reserve with default values
+    pub fn new() -> Self {
+        let mut reserve = Reserve::default();
+        reserve.version = RESERVE_VERSION;
+        reserve.lending_market = Pubkey::new_unique();
+        reserve.liquidity.mint_pubkey = Pubkey::new_unique();
+        reserve.liquidity.mint_decimals = 9;
+        reserve.liquidity.supply_pubkey = Pubkey::new_unique();
+        reserve.lp_tokens_info.mint_pubkey = Pubkey::new_unique();
+        reserve.lp_tokens_info.supply_pubkey = Pubkey::new_unique();
+        reserve.config.fees.texture_fee_percentage = 20;
+        reserve.config.deposit_limit = u64::MAX;
+        reserve.config.fee_receiver = Pubkey::new_unique();
+
+        Self { reserve }.fee_bps(30)
+    }
+
+    /// Total flash loan fee in basis points, 30 = 0.3%
+    pub fn fee_bps(mut self, fee_bps: u16) -> Self {
+        self.reserve.config.fees.flash_loan_fee_wad = Rate::from_bps(fee_bps)
+            .to_wad_u64()
+            .expect("Basis points fit into a wad");
+        self
+    }
+
+    /// Part of the flash loan fee going to Texture, in %
+    pub fn texture_fee_percentage(mut self, texture_fee_percentage: u8) -> Self {
+        self.reserve.config.fees.texture_fee_percentage = texture_fee_percentage;
+        self
+    }
+
+    /// Reserve liquidity mint decimals
+    pub fn decimals(mut self, decimals: u8) -> Self {
+        self.reserve.liquidity.mint_decimals = decimals as u64;
+        self
+    }
+
+    /// Liquidity available for flash loans, in native units. Also used as the LP tokens supply.
+    pub fn available_liquidity(mut self, available_amount: u64) -> Self {
+        self.reserve.liquidity.available_amount = available_amount;
+        self.reserve.lp_tokens_info.mint_total_supply = available_amount;
+        self
+    }
+
+    /// Maximum deposit limit, in native units
+    pub fn deposit_limit(mut self, deposit_limit: u64) -> Self {
+        self.reserve.config.deposit_limit = deposit_limit;
+        self
+    }
+
+    /// Lending market address
+    pub fn lending_market(mut self, lending_market: Pubkey) -> Self {
+        self.reserve.lending_market = lending_market;
+        self
+    }
+
+    /// Reserve liquidity mint address
+    pub fn mint(mut self, mint: Pubkey) -> Self {
+        self.reserve.liquidity.mint_pubkey = mint;
+        self
+    }
+
+    /// Reserve liquidity supply address
+    pub fn supply(mut self, supply: Pubkey) -> Self {
+        self.reserve.liquidity.supply_pubkey = supply;
+        self
+    }
+
+    /// Flash loan fee receiver address
+    pub fn fee_receiver(mut self, fee_receiver: Pubkey) -> Self {
+        self.reserve.config.fee_receiver = fee_receiver;
+        self
+    }
+
+    /// Last slot when the reserve was updated
+    pub fn last_update(mut self, slot: Slot) -> Self {
+        self.reserve.last_update = slot;
+        self
+    }
+
+    /// Returns the reserve
+    pub fn build(&self) -> Reserve {
+        self.reserve
+    }
+
+    /// Returns the reserve packed into account data
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0; Reserve::LEN];
+        self.reserve.pack_into_slice(&mut data);
+        data
+    }
+
+    /// Returns a rent exempt reserve account owned by the Flash Loan program
+    pub fn account(&self) -> Account {
+        Account {
+            lamports: Rent::default().minimum_balance(Reserve::LEN),
+            data: self.pack(),
+            owner: FLASH_LOAN_ID,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flash_loan_fee;
+
+    #[test]
+    fn build_reserve() {
+        let mint = Pubkey::new_unique();
+        let reserve = ReserveBuilder::new()
+            .fee_bps(25)
+            .texture_fee_percentage(50)
+            .decimals(6)
+            .available_liquidity(1_000_000)
+            .deposit_limit(5_000_000)
+            .mint(mint)
+            .build();
+
+        assert!(reserve.version > 0);
+        assert_eq!(
+            reserve.config.fees.flash_loan_fee_wad,
+            2_500_000_000_000_000
+        );
+        assert_eq!(reserve.config.fees.texture_fee_percentage, 50);
+        assert_eq!(reserve.liquidity.mint_decimals, 6);
+        assert_eq!(reserve.liquidity.mint_pubkey, mint);
+        assert_eq!(reserve.liquidity.available_amount, 1_000_000);
+        assert_eq!(reserve.config.deposit_limit, 5_000_000);
+        assert_eq!(flash_loan_fee(&reserve, 1_000_000).unwrap(), 2_500);
+    }
+
+    #[test]
+    fn reserve_account() {
+        let builder = ReserveBuilder::new().available_liquidity(42);
+        let account = builder.account();
+
+        assert_eq!(account.owner, FLASH_LOAN_ID);
+        assert!(Rent::default().is_exempt(account.lamports, account.data.len()));
+        assert_eq!(Reserve::unpack(&account.data).unwrap(), builder.build());
+    }
+}
@@ -0,0 +1,114 @@
+//! SPL Token and Token-2022 helpers
+
+use solana_program::clock::Epoch;
+use solana_program::pubkey::Pubkey;
+use spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use spl_token_2022::state::Mint;
+
+use crate::error::{FlashProgramError, FlashSdkError};
+use crate::fetcher::AccountFetcher;
+
+/// Returns the token program owning `mint`: SPL Token or Token-2022
+pub fn token_program_for_mint(
+    fetcher: &dyn AccountFetcher,
+    mint: &Pubkey,
+) -> Result<Pubkey, FlashSdkError> {
+    let account = fetcher.get_account(mint)?.ok_or(FlashSdkError::FlashError(
+        FlashProgramError::InvalidTokenMint,
+    ))?;
+
+    if account.owner == spl_token::id() || account.owner == spl_token_2022::id() {
+        Ok(account.owner)
+    } else {
+        Err(FlashSdkError::UnsupportedTokenProgram(account.owner))
+    }
+}
+
+/// Returns the fee withheld by a Token-2022 transfer fee extension when `amount` is transferred
+/// in `epoch`. Zero for SPL Token mints and Token-2022 mints without the extension.
+/// Rounded up like Token-2022 does.
+pub fn transfer_fee(mint_data: &[u8], epoch: Epoch, amount: u64) -> Result<u64, FlashSdkError> {
+    let mint = StateWithExtensions::<Mint>::unpack(mint_data)
+        .map_err(|_| FlashSdkError::FlashError(FlashProgramError::InvalidTokenMint))?;
+
+    match mint.get_extension::<TransferFeeConfig>() {
+        Ok(transfer_fee_config) => transfer_fee_config
+            .calculate_epoch_fee(epoch, amount)
+            .ok_or(FlashSdkError::FlashError(FlashProgramError::MathOverflow)),
+        Err(_) => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use solana_program::program_pack::Pack;
+    use solana_sdk::account::Account;
+
+    use super::*;
+    use crate::testing::mint_with_transfer_fee;
+
+    #[test]
+    fn infer_token_program() {
+        let spl_mint = Pubkey::new_unique();
+        let token_2022_mint = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+        for (mint, owner) in [
+            (spl_mint, spl_token::id()),
+            (token_2022_mint, spl_token_2022::id()),
+            (other_mint, other_program),
+        ]
+        .iter()
+        {
+            accounts.insert(
+                *mint,
+                Account {
+                    owner: *owner,
+                    ..Account::default()
+                },
+            );
+        }
+
+        assert_eq!(
+            token_program_for_mint(&accounts, &spl_mint),
+            Ok(spl_token::id())
+        );
+        assert_eq!(
+            token_program_for_mint(&accounts, &token_2022_mint),
+            Ok(spl_token_2022::id())
+        );
+        assert_eq!(
+            token_program_for_mint(&accounts, &other_mint),
+            Err(FlashSdkError::UnsupportedTokenProgram(other_program))
+        );
+        assert_eq!(
+            token_program_for_mint(&accounts, &Pubkey::new_unique()),
+            Err(FlashSdkError::FlashError(
+                FlashProgramError::InvalidTokenMint
+            ))
+        );
+    }
+
+    #[test]
+    fn mint_transfer_fee() {
+        let mut spl_mint = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            is_initialized: true,
+            ..spl_token::state::Mint::default()
+        }
+        .pack_into_slice(&mut spl_mint);
+        assert_eq!(transfer_fee(&spl_mint, 0, 1_000_000), Ok(0));
+
+        let mint = mint_with_transfer_fee(6, 50, 1_000);
+        assert_eq!(transfer_fee(&mint, 0, 100_000), Ok(500));
+        assert_eq!(transfer_fee(&mint, 0, 1_000_000), Ok(1_000));
+        assert_eq!(transfer_fee(&mint, 0, 0), Ok(0));
+        // Rounded up
+        assert_eq!(transfer_fee(&mint, 0, 1), Ok(1));
+        assert_eq!(transfer_fee(&mint, 0, 201), Ok(2));
+    }
+}
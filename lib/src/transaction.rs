@@ -0,0 +1,451 @@
+//! Transaction assembly helpers
+
+use solana_address_lookup_table_program::instruction::{create_lookup_table, extend_lookup_table};
+use solana_address_lookup_table_program::state::AddressLookupTable;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_program::address_lookup_table_account::AddressLookupTableAccount;
+use solana_program::clock::Slot;
+use solana_program::hash::Hash;
+use solana_program::instruction::Instruction;
+use solana_program::message::{v0, VersionedMessage};
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar;
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::signature::Signature;
+use solana_sdk::signers::Signers;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+
+use crate::builder::FlashLoanInstructions;
+use crate::error::FlashSdkError;
+use crate::fetcher::AccountFetcher;
+use crate::instruction::find_lending_market_authority;
+use crate::types::Reserve;
+
+/// Maximum compute unit limit of a transaction
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Compute budget instructions to put in front of a transaction.
+///
+/// They are prepended to the transaction, which shifts the index of every other instruction.
+/// `FlashBorrow` and `FlashRepay` find each other through the instructions sysvar relative to
+/// their own index, so the pair stays valid wherever it ends up in the transaction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComputeBudget {
+    /// Compute unit limit, the runtime default when `None`
+    pub unit_limit: Option<u32>,
+    /// Priority fee in micro-lamports per compute unit, no priority fee when `None`
+    pub unit_price: Option<u64>,
+}
+
+impl ComputeBudget {
+    /// Returns the compute budget instructions
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = vec![];
+        if let Some(unit_limit) = self.unit_limit {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(unit_limit));
+        }
+        if let Some(unit_price) = self.unit_price {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(unit_price));
+        }
+        instructions
+    }
+
+    /// Returns the compute budget instructions followed by `instructions`. Compute budget
+    /// instructions of `instructions` setting what this budget sets are replaced, since the
+    /// runtime rejects a transaction setting the same compute budget parameter twice.
+    pub fn with_instructions(&self, instructions: &[Instruction]) -> Vec<Instruction> {
+        let mut result = self.instructions();
+        // Compute budget instructions are told apart by their first byte
+        let replaced = result
+            .iter()
+            .map(|instruction| instruction.data[0])
+            .collect::<Vec<_>>();
+        let kept = instructions.iter().filter(|instruction| {
+            instruction.program_id != compute_budget::id()
+                || !matches!(instruction.data.first(), Some(tag) if replaced.contains(tag))
+        });
+        result.extend(kept.cloned());
+        result
+    }
+
+    /// Sets the compute unit limit to `units_consumed` in a simulation plus `margin_percent`
+    /// percent rounded up, capped at [MAX_COMPUTE_UNIT_LIMIT](constant.MAX_COMPUTE_UNIT_LIMIT.html).
+    pub fn unit_limit_from_simulation(mut self, units_consumed: u64, margin_percent: u8) -> Self {
+        let unit_limit = units_consumed
+            .saturating_mul(100 + margin_percent as u64)
+            .saturating_add(99)
+            / 100;
+        self.unit_limit = Some(unit_limit.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32);
+        self
+    }
+
+    /// Simulates `instructions` with the maximum compute unit limit and sizes the limit from the
+    /// consumed compute units, see [unit_limit_from_simulation](#method.unit_limit_from_simulation).
+    /// The simulation skips signature verification and uses the latest blockhash.
+    pub fn unit_limit_from_rpc(
+        self,
+        rpc_client: &RpcClient,
+        payer: &Pubkey,
+        instructions: &[Instruction],
+        lookup_tables: &[AddressLookupTableAccount],
+        margin_percent: u8,
+    ) -> Result<Self, FlashSdkError> {
+        let budget = ComputeBudget {
+            unit_limit: Some(MAX_COMPUTE_UNIT_LIMIT),
+            ..self
+        };
+        let message = v0::Message::try_compile(
+            payer,
+            &budget.with_instructions(instructions),
+            lookup_tables,
+            Hash::default(),
+        )
+        .map_err(|_| FlashSdkError::TransactionCompileError)?;
+        let transaction = VersionedTransaction {
+            signatures: vec![Signature::default(); message.header.num_required_signatures as usize],
+            message: VersionedMessage::V0(message),
+        };
+
+        let result = rpc_client
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+            .map_err(|_| FlashSdkError::RpcError)?
+            .value;
+        if let Some(err) = result.err {
+            return Err(FlashSdkError::TransactionError(err));
+        }
+        let units_consumed = result.units_consumed.ok_or(FlashSdkError::RpcError)?;
+
+        Ok(self.unit_limit_from_simulation(units_consumed, margin_percent))
+    }
+}
+
+/// Returns every account of flash loan instructions for `reserve` which does not depend on the
+/// user: liquidity supply, fee receiver, reserve, lending market, lending market authority,
+/// instructions sysvar and token program.
+pub fn flash_loan_lookup_table_addresses(
+    program_id: &Pubkey,
+    reserve_pubkey: &Pubkey,
+    reserve: &Reserve,
+    token_program_id: &Pubkey,
+) -> Vec<Pubkey> {
+    vec![
+        reserve.liquidity.supply_pubkey,
+        reserve.config.fee_receiver,
+        *reserve_pubkey,
+        reserve.lending_market,
+        find_lending_market_authority(program_id, &reserve.lending_market),
+        sysvar::instructions::id(),
+        *token_program_id,
+    ]
+}
+
+/// Creates instructions which create an address lookup table owned by `authority` and fill it
+/// with `addresses`. Returns the instructions and the lookup table address.
+///
+/// `recent_slot` must be a recent slot, e.g. fetched with `RpcClient::get_slot`. The table can be
+/// used in transactions starting from the slot after the extension.
+pub fn create_lookup_table_instructions(
+    authority: Pubkey,
+    payer: Pubkey,
+    recent_slot: Slot,
+    addresses: Vec<Pubkey>,
+) -> (Vec<Instruction>, Pubkey) {
+    let (create, lookup_table) = create_lookup_table(authority, payer, recent_slot);
+    let extend = extend_lookup_table(lookup_table, authority, Some(payer), addresses);
+    (vec![create, extend], lookup_table)
+}
+
+/// Loads the address lookup table at `address`
+pub fn get_lookup_table(
+    fetcher: &dyn AccountFetcher,
+    address: &Pubkey,
+) -> Result<AddressLookupTableAccount, FlashSdkError> {
+    let account = fetcher
+        .get_account(address)?
+        .filter(|account| account.owner == solana_address_lookup_table_program::id())
+        .ok_or(FlashSdkError::DeserializationError)?;
+    let lookup_table = AddressLookupTable::deserialize(&account.data)
+        .map_err(|_| FlashSdkError::DeserializationError)?;
+
+    Ok(AddressLookupTableAccount {
+        key: *address,
+        addresses: lookup_table.addresses.to_vec(),
+    })
+}
+
+/// Compiles `instructions` preceded by `compute_budget` instructions into a signed v0
+/// transaction resolving accounts through `lookup_tables`.
+pub fn versioned_transaction(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    compute_budget: &ComputeBudget,
+    lookup_tables: &[AddressLookupTableAccount],
+    recent_blockhash: Hash,
+    signers: &impl Signers,
+) -> Result<VersionedTransaction, FlashSdkError> {
+    let message = v0::Message::try_compile(
+        payer,
+        &compute_budget.with_instructions(instructions),
+        lookup_tables,
+        recent_blockhash,
+    )
+    .map_err(|_| FlashSdkError::TransactionCompileError)?;
+
+    VersionedTransaction::try_new(VersionedMessage::V0(message), signers)
+        .map_err(|_| FlashSdkError::SigningError)
+}
+
+/// Assembles a flash loan with `instructions` using the borrowed liquidity into a signed v0
+/// transaction, see [versioned_transaction](fn.versioned_transaction.html).
+pub fn flash_loan_versioned_transaction(
+    payer: &Pubkey,
+    flash_loan: FlashLoanInstructions,
+    instructions: impl IntoIterator<Item = Instruction>,
+    compute_budget: &ComputeBudget,
+    lookup_tables: &[AddressLookupTableAccount],
+    recent_blockhash: Hash,
+    signers: &impl Signers,
+) -> Result<VersionedTransaction, FlashSdkError> {
+    versioned_transaction(
+        payer,
+        &flash_loan.with_instructions(instructions),
+        compute_budget,
+        lookup_tables,
+        recent_blockhash,
+        signers,
+    )
+}
+
+/// Assembles a flash loan with `instructions` using the borrowed liquidity into a signed legacy
+/// transaction preceded by `compute_budget` instructions.
+pub fn flash_loan_transaction(
+    payer: &Pubkey,
+    flash_loan: FlashLoanInstructions,
+    instructions: impl IntoIterator<Item = Instruction>,
+    compute_budget: &ComputeBudget,
+    recent_blockhash: Hash,
+    signers: &impl Signers,
+) -> Result<Transaction, FlashSdkError> {
+    let mut transaction = Transaction::new_with_payer(
+        &compute_budget.with_instructions(&flash_loan.with_instructions(instructions)),
+        Some(payer),
+    );
+    transaction
+        .try_sign(signers, recent_blockhash)
+        .map_err(|_| FlashSdkError::SigningError)?;
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+
+    use solana_address_lookup_table_program::state::LookupTableMeta;
+    use solana_sdk::account::Account;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    use super::*;
+    use crate::builder::FlashLoanBuilder;
+    use crate::flash_loan_fee;
+    use crate::testing::{FlashLoanFixture, FlashLoanFixtureConfig, ReserveBuilder};
+    use crate::FLASH_LOAN_ID;
+
+    #[test]
+    fn flash_loan_with_lookup_table() {
+        let reserve_pubkey = Pubkey::new_unique();
+        let reserve = ReserveBuilder::new().available_liquidity(1_000_000).build();
+        let authority = Keypair::new();
+        let flash_loan = FlashLoanBuilder::new(
+            FLASH_LOAN_ID,
+            reserve_pubkey,
+            &reserve,
+            10_000,
+            authority.pubkey(),
+        )
+        .liquidity_account(Pubkey::new_unique())
+        .build()
+        .unwrap();
+        let addresses = flash_loan_lookup_table_addresses(
+            &FLASH_LOAN_ID,
+            &reserve_pubkey,
+            &reserve,
+            &spl_token::id(),
+        );
+        let lookup_table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: addresses.clone(),
+        };
+
+        let transaction = flash_loan_versioned_transaction(
+            &authority.pubkey(),
+            flash_loan.clone(),
+            vec![],
+            &ComputeBudget::default(),
+            std::slice::from_ref(&lookup_table),
+            Hash::new_unique(),
+            &[&authority],
+        )
+        .unwrap();
+        transaction.verify_and_hash_message().unwrap();
+
+        let message = match &transaction.message {
+            VersionedMessage::V0(message) => message,
+            VersionedMessage::Legacy(_) => panic!("legacy message"),
+        };
+        assert_eq!(message.address_table_lookups.len(), 1);
+        assert_eq!(
+            message.address_table_lookups[0].account_key,
+            lookup_table.key
+        );
+        for address in &addresses {
+            assert!(!message.account_keys.contains(address));
+        }
+
+        let legacy = Transaction::new_with_payer(
+            &flash_loan.with_instructions(vec![]),
+            Some(&authority.pubkey()),
+        );
+        assert!(transaction.message.serialize().len() < legacy.message.serialize().len());
+    }
+
+    #[test]
+    fn load_lookup_table() {
+        let address = Pubkey::new_unique();
+        let addresses = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let data = AddressLookupTable {
+            meta: LookupTableMeta::default(),
+            addresses: Cow::Borrowed(&addresses),
+        }
+        .serialize_for_tests()
+        .unwrap();
+        let accounts = vec![(
+            address,
+            Account {
+                data,
+                owner: solana_address_lookup_table_program::id(),
+                ..Account::default()
+            },
+        )]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+        assert_eq!(
+            get_lookup_table(&accounts, &address),
+            Ok(AddressLookupTableAccount {
+                key: address,
+                addresses
+            })
+        );
+        assert_eq!(
+            get_lookup_table(&accounts, &Pubkey::new_unique()),
+            Err(FlashSdkError::DeserializationError)
+        );
+    }
+
+    #[test]
+    fn create_and_extend_lookup_table() {
+        let authority = Pubkey::new_unique();
+        let addresses = vec![Pubkey::new_unique()];
+        let (instructions, lookup_table) =
+            create_lookup_table_instructions(authority, authority, 42, addresses);
+
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].accounts[0].pubkey, lookup_table);
+        assert_eq!(instructions[1].accounts[0].pubkey, lookup_table);
+    }
+
+    #[test]
+    fn compute_budget_instructions() {
+        assert!(ComputeBudget::default().instructions().is_empty());
+
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let budget = ComputeBudget {
+            unit_limit: Some(200_000),
+            unit_price: Some(1_000),
+        };
+        assert_eq!(
+            budget.with_instructions(std::slice::from_ref(&instruction)),
+            vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+                ComputeBudgetInstruction::set_compute_unit_price(1_000),
+                instruction.clone(),
+            ]
+        );
+
+        // Only what the budget sets is replaced
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(50_000),
+            ComputeBudgetInstruction::request_heap_frame(64 * 1024),
+            instruction.clone(),
+            ComputeBudgetInstruction::set_compute_unit_price(5),
+        ];
+        let budget = ComputeBudget {
+            unit_limit: Some(200_000),
+            unit_price: None,
+        };
+        assert_eq!(
+            budget.with_instructions(&instructions),
+            vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+                ComputeBudgetInstruction::request_heap_frame(64 * 1024),
+                instruction,
+                ComputeBudgetInstruction::set_compute_unit_price(5),
+            ]
+        );
+        assert_eq!(
+            ComputeBudget::default().with_instructions(&instructions),
+            instructions
+        );
+    }
+
+    #[test]
+    fn unit_limit_from_simulation() {
+        let budget = ComputeBudget {
+            unit_limit: None,
+            unit_price: Some(1_000),
+        };
+
+        assert_eq!(
+            budget.unit_limit_from_simulation(40_000, 10),
+            ComputeBudget {
+                unit_limit: Some(44_000),
+                unit_price: Some(1_000),
+            }
+        );
+        assert_eq!(budget.unit_limit_from_simulation(1, 10).unit_limit, Some(2));
+        assert_eq!(
+            budget.unit_limit_from_simulation(1_300_000, 20).unit_limit,
+            Some(MAX_COMPUTE_UNIT_LIMIT)
+        );
+    }
+
+    #[tokio::test]
+    async fn flash_loan_after_compute_budget() {
+        let mut fixture = FlashLoanFixture::start(FlashLoanFixtureConfig::default()).await;
+        let reserve = fixture.reserve().await;
+        let amount = 1_000_000_000;
+        let fee = flash_loan_fee(&reserve, amount).unwrap();
+        let user_liquidity = fixture.token_balance(fixture.user_liquidity).await;
+
+        let instructions = ComputeBudget {
+            unit_limit: Some(200_000),
+            unit_price: Some(1),
+        }
+        .with_instructions(&[fixture.flash_borrow(amount), fixture.flash_repay(amount)]);
+        fixture.process_transaction(&instructions).await.unwrap();
+
+        assert_eq!(
+            fixture.token_balance(fixture.user_liquidity).await,
+            user_liquidity - fee
+        );
+    }
+}
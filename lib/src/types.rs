@@ -1,22 +1,34 @@
 //! Types of on-chain objects
 
+use std::fmt;
+
 use bytemuck::{try_from_bytes, try_from_bytes_mut, Pod, Zeroable};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use solana_program::clock::Slot;
 use solana_program::program_error::ProgramError;
 use solana_program::program_pack::{IsInitialized, Pack, Sealed};
 use solana_program::pubkey::Pubkey;
 
+use crate::amount::amount_to_ui_amount;
+
+/// Version of the Reserve layout supported by this SDK
+pub const RESERVE_VERSION: u8 = 1;
+
 /// Lending market reserve state
 #[derive(Clone, Debug, Default, PartialEq, Eq, Copy, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 pub struct Reserve {
     /// Version of the struct
     pub version: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
     _padding: [u8; 7],
 
     /// Last slot when liquidity supply and\or LP tokens supply updated
     pub last_update: Slot,
     /// Lending market address
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::pubkey"))]
     pub lending_market: Pubkey,
     /// Reserve liquidity
     pub liquidity: ReserveLiquidity,
@@ -25,18 +37,22 @@ pub struct Reserve {
     /// Reserve configuration values
     pub config: ReserveConfig,
 
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub _future_padding: [u64; 5],
 }
 
 /// Reserve liquidity
 #[derive(Clone, Debug, Default, PartialEq, Eq, Copy, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 pub struct ReserveLiquidity {
     /// Reserve liquidity mint address
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::pubkey"))]
     pub mint_pubkey: Pubkey,
     /// Reserve liquidity mint decimals
     pub mint_decimals: u64,
     /// Reserve liquidity supply address
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::pubkey"))]
     pub supply_pubkey: Pubkey,
     /// Reserve liquidity available
     pub available_amount: u64,
@@ -44,18 +60,22 @@ pub struct ReserveLiquidity {
 
 /// Reserve Liquidity Provider (LP) tokens info.
 #[derive(Clone, Debug, Default, PartialEq, Eq, Copy, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 pub struct ReserveLpTokens {
     /// Reserve LP mint address
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::pubkey"))]
     pub mint_pubkey: Pubkey,
     /// Reserve LP mint supply, used for exchange rate
     pub mint_total_supply: u64,
     /// Reserve LP supply address
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::pubkey"))]
     pub supply_pubkey: Pubkey,
 }
 
 /// Reserve configuration values
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 pub struct ReserveConfig {
     /// Fees for providing flash loan
@@ -63,15 +83,19 @@ pub struct ReserveConfig {
     /// Maximum deposit limit of liquidity in native units, u64::MAX for inf
     pub deposit_limit: u64,
     /// Flash loan fee receiver address (usually Texture treasury wallet)
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::pubkey"))]
     pub fee_receiver: Pubkey,
 
     // Space for future expansion
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub _future_padding1: [u8; 32],
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub _future_padding2: [u8; 32],
 }
 
 /// Fee information on a reserve
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 pub struct ReserveFees {
     /// Total fee for flash loan, expressed as a Wad.
@@ -81,6 +105,7 @@ pub struct ReserveFees {
     /// Amount of flash loan fee going to Texture. Rest of flash_loan_fee_wad goes to reserve's liquidity pool
     /// 1% = 1
     pub texture_fee_percentage: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub _padding: [u8; 7],
 }
 
@@ -112,3 +137,280 @@ impl Pack for Reserve {
         Ok(*reserve)
     }
 }
+
+impl ReserveFees {
+    /// Flash loan fee in percent, e.g. "0.3"
+    pub fn flash_loan_fee_percent(&self) -> String {
+        // The fee is a fraction with 18 decimals, so as percent it has 16
+        amount_to_ui_amount(self.flash_loan_fee_wad, 16)
+    }
+}
+
+impl ReserveConfig {
+    /// Formats the deposit limit in UI units of a mint with `decimals`, `"unlimited"` for
+    /// `u64::MAX`
+    pub fn deposit_limit_ui_amount(&self, decimals: u8) -> String {
+        if self.deposit_limit == u64::MAX {
+            "unlimited".to_string()
+        } else {
+            amount_to_ui_amount(self.deposit_limit, decimals)
+        }
+    }
+}
+
+impl fmt::Display for Reserve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = self.liquidity.decimals();
+        writeln!(
+            f,
+            "Reserve v{}, last updated at slot {}",
+            self.version, self.last_update
+        )?;
+        writeln!(f, "  Lending market:      {}", self.lending_market)?;
+        writeln!(
+            f,
+            "  Mint:                {} ({} decimals)",
+            self.liquidity.mint_pubkey, decimals
+        )?;
+        writeln!(f, "  Supply:              {}", self.liquidity.supply_pubkey)?;
+        writeln!(
+            f,
+            "  Available liquidity: {}",
+            self.liquidity
+                .amount_to_ui_amount(self.liquidity.available_amount)
+        )?;
+        writeln!(
+            f,
+            "  Deposit limit:       {}",
+            self.config.deposit_limit_ui_amount(decimals)
+        )?;
+        writeln!(
+            f,
+            "  Flash loan fee:      {}% ({}% to Texture)",
+            self.config.fees.flash_loan_fee_percent(),
+            self.config.fees.texture_fee_percentage
+        )?;
+        write!(f, "  Fee receiver:        {}", self.config.fee_receiver)
+    }
+}
+
+/// Changed field of a reserve, see [Reserve::diff](struct.Reserve.html#method.diff)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReserveChange {
+    /// Path of the field, e.g. `liquidity.available_amount`
+    pub field: &'static str,
+    /// Old value formatted like in `Display` of the reserve
+    pub old: String,
+    /// New value formatted like in `Display` of the reserve
+    pub new: String,
+}
+
+impl fmt::Display for ReserveChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+impl Reserve {
+    /// Lists the fields which differ in `other`, ignoring padding. Amounts are in UI units and
+    /// the fee in percent.
+    pub fn diff(&self, other: &Reserve) -> Vec<ReserveChange> {
+        let mut changes = Vec::new();
+        let mut compare = |field: &'static str, changed: bool, old: String, new: String| {
+            if changed {
+                changes.push(ReserveChange { field, old, new });
+            }
+        };
+        let (old, new) = (self, other);
+
+        compare(
+            "version",
+            old.version != new.version,
+            old.version.to_string(),
+            new.version.to_string(),
+        );
+        compare(
+            "last_update",
+            old.last_update != new.last_update,
+            old.last_update.to_string(),
+            new.last_update.to_string(),
+        );
+        compare(
+            "lending_market",
+            old.lending_market != new.lending_market,
+            old.lending_market.to_string(),
+            new.lending_market.to_string(),
+        );
+        compare(
+            "liquidity.mint_pubkey",
+            old.liquidity.mint_pubkey != new.liquidity.mint_pubkey,
+            old.liquidity.mint_pubkey.to_string(),
+            new.liquidity.mint_pubkey.to_string(),
+        );
+        compare(
+            "liquidity.mint_decimals",
+            old.liquidity.mint_decimals != new.liquidity.mint_decimals,
+            old.liquidity.mint_decimals.to_string(),
+            new.liquidity.mint_decimals.to_string(),
+        );
+        compare(
+            "liquidity.supply_pubkey",
+            old.liquidity.supply_pubkey != new.liquidity.supply_pubkey,
+            old.liquidity.supply_pubkey.to_string(),
+            new.liquidity.supply_pubkey.to_string(),
+        );
+        compare(
+            "liquidity.available_amount",
+            old.liquidity.available_amount != new.liquidity.available_amount,
+            old.liquidity
+                .amount_to_ui_amount(old.liquidity.available_amount),
+            new.liquidity
+                .amount_to_ui_amount(new.liquidity.available_amount),
+        );
+        compare(
+            "lp_tokens_info.mint_pubkey",
+            old.lp_tokens_info.mint_pubkey != new.lp_tokens_info.mint_pubkey,
+            old.lp_tokens_info.mint_pubkey.to_string(),
+            new.lp_tokens_info.mint_pubkey.to_string(),
+        );
+        compare(
+            "lp_tokens_info.mint_total_supply",
+            old.lp_tokens_info.mint_total_supply != new.lp_tokens_info.mint_total_supply,
+            old.lp_tokens_info.mint_total_supply.to_string(),
+            new.lp_tokens_info.mint_total_supply.to_string(),
+        );
+        compare(
+            "lp_tokens_info.supply_pubkey",
+            old.lp_tokens_info.supply_pubkey != new.lp_tokens_info.supply_pubkey,
+            old.lp_tokens_info.supply_pubkey.to_string(),
+            new.lp_tokens_info.supply_pubkey.to_string(),
+        );
+        compare(
+            "config.fees.flash_loan_fee_wad",
+            old.config.fees.flash_loan_fee_wad != new.config.fees.flash_loan_fee_wad,
+            format!("{}%", old.config.fees.flash_loan_fee_percent()),
+            format!("{}%", new.config.fees.flash_loan_fee_percent()),
+        );
+        compare(
+            "config.fees.texture_fee_percentage",
+            old.config.fees.texture_fee_percentage != new.config.fees.texture_fee_percentage,
+            format!("{}%", old.config.fees.texture_fee_percentage),
+            format!("{}%", new.config.fees.texture_fee_percentage),
+        );
+        compare(
+            "config.deposit_limit",
+            old.config.deposit_limit != new.config.deposit_limit,
+            old.config.deposit_limit_ui_amount(old.liquidity.decimals()),
+            new.config.deposit_limit_ui_amount(new.liquidity.decimals()),
+        );
+        compare(
+            "config.fee_receiver",
+            old.config.fee_receiver != new.config.fee_receiver,
+            old.config.fee_receiver.to_string(),
+            new.config.fee_receiver.to_string(),
+        );
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::ReserveBuilder;
+
+    #[test]
+    fn display_reserve() {
+        let builder = ReserveBuilder::new()
+            .fee_bps(30)
+            .texture_fee_percentage(20)
+            .decimals(6)
+            .available_liquidity(1_500_000)
+            .deposit_limit(u64::MAX);
+        let reserve = builder.build();
+
+        let display = reserve.to_string();
+        assert!(display.starts_with("Reserve v1, last updated at slot "));
+        for line in [
+            format!("  Lending market:      {}", reserve.lending_market),
+            format!(
+                "  Mint:                {} (6 decimals)",
+                reserve.liquidity.mint_pubkey
+            ),
+            format!("  Supply:              {}", reserve.liquidity.supply_pubkey),
+            "  Available liquidity: 1.5".to_string(),
+            "  Deposit limit:       unlimited".to_string(),
+            "  Flash loan fee:      0.3% (20% to Texture)".to_string(),
+            format!("  Fee receiver:        {}", reserve.config.fee_receiver),
+        ]
+        .iter()
+        {
+            assert!(display.lines().any(|l| l == line), "{}", line);
+        }
+        assert!(!display.ends_with('\n'));
+
+        let limited = builder.deposit_limit(2_000_000).build().to_string();
+        assert!(limited.contains("  Deposit limit:       2\n"));
+    }
+
+    #[test]
+    fn diff_reserves() {
+        let builder = ReserveBuilder::new()
+            .fee_bps(30)
+            .decimals(9)
+            .available_liquidity(1_000_000_000)
+            .last_update(10);
+        let reserve = builder.build();
+        assert!(reserve.diff(&reserve).is_empty());
+
+        let fee_receiver = Pubkey::new_unique();
+        let updated = builder
+            .clone()
+            .fee_bps(5)
+            .available_liquidity(2_500_000_000)
+            .last_update(11)
+            .fee_receiver(fee_receiver)
+            .build();
+        let changes = reserve.diff(&updated);
+        assert_eq!(
+            changes,
+            vec![
+                ReserveChange {
+                    field: "last_update",
+                    old: "10".to_string(),
+                    new: "11".to_string(),
+                },
+                ReserveChange {
+                    field: "liquidity.available_amount",
+                    old: "1".to_string(),
+                    new: "2.5".to_string(),
+                },
+                // The builder keeps LP tokens 1:1 with the liquidity
+                ReserveChange {
+                    field: "lp_tokens_info.mint_total_supply",
+                    old: "1000000000".to_string(),
+                    new: "2500000000".to_string(),
+                },
+                ReserveChange {
+                    field: "config.fees.flash_loan_fee_wad",
+                    old: "0.3%".to_string(),
+                    new: "0.05%".to_string(),
+                },
+                ReserveChange {
+                    field: "config.fee_receiver",
+                    old: reserve.config.fee_receiver.to_string(),
+                    new: fee_receiver.to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            changes[3].to_string(),
+            "config.fees.flash_loan_fee_wad: 0.3% -> 0.05%"
+        );
+
+        // Padding is not a field
+        let mut padded = reserve;
+        padded._future_padding = [1; 5];
+        assert!(reserve.diff(&padded).is_empty());
+    }
+}
@@ -0,0 +1,458 @@
+//! Real-time reserve updates over a websocket account subscription
+//!
+//! [ReserveWatcher](struct.ReserveWatcher.html) subscribes to a reserve account with
+//! `PubsubClient::account_subscribe`, decodes every update into a `Reserve` and reports what
+//! changed as [ReserveEvent](enum.ReserveEvent.html)s. When the connection is lost it
+//! resubscribes after `reconnect_delay`, comparing the next update with the last known state.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crossbeam_channel::RecvTimeoutError;
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::pubsub_client::{PubsubAccountClientSubscription, PubsubClient};
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+
+use crate::error::FlashSdkError;
+use crate::types::{Reserve, ReserveFees};
+use crate::unpack_reserve;
+
+/// How often the watcher checks whether it is stopped while waiting for updates
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Settings of a [ReserveWatcher](struct.ReserveWatcher.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReserveWatcherConfig {
+    /// Commitment of account updates
+    pub commitment: CommitmentConfig,
+    /// Delay before resubscribing after the connection is lost or cannot be established
+    pub reconnect_delay: Duration,
+}
+
+impl Default for ReserveWatcherConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentConfig::confirmed(),
+            reconnect_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Change of a watched reserve
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReserveEvent {
+    /// Subscription to the reserve account is established, also after reconnecting
+    Subscribed,
+    /// Connection is lost, the watcher resubscribes after `reconnect_delay`
+    Disconnected,
+    /// Liquidity available for flash loans changed
+    LiquidityChanged { old: u64, new: u64 },
+    /// Flash loan fee or its Texture share changed
+    FeeChanged { old: ReserveFees, new: ReserveFees },
+    /// Account update is not a valid reserve
+    InvalidUpdate(FlashSdkError),
+}
+
+/// State shared with the background thread
+#[derive(Default)]
+struct Shared {
+    exit: AtomicBool,
+    /// Current subscription, shut down when the watcher is dropped
+    subscription: Mutex<Option<PubsubAccountClientSubscription>>,
+}
+
+impl Shared {
+    fn exit(&self) -> bool {
+        self.exit.load(Ordering::Relaxed)
+    }
+
+    fn shutdown_subscription(&self) {
+        let subscription = self.subscription.lock().unwrap().take();
+        if let Some(mut subscription) = subscription {
+            let _ = subscription.shutdown();
+        }
+    }
+}
+
+/// Watches a reserve account in a background thread until dropped
+pub struct ReserveWatcher {
+    events: Receiver<ReserveEvent>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ReserveWatcher {
+    /// Starts watching `reserve_pubkey` of `program_id` via websocket `url`. `reserve` is the
+    /// last known state, e.g. from [get_reserve](../fn.get_reserve.html), which the first update
+    /// is compared with.
+    pub fn start(
+        url: &str,
+        program_id: Pubkey,
+        reserve_pubkey: Pubkey,
+        reserve: Option<Reserve>,
+        config: ReserveWatcherConfig,
+    ) -> Self {
+        let (sender, events) = channel();
+        let shared = Arc::new(Shared::default());
+
+        let url = url.to_string();
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || {
+            watch(
+                &url,
+                &program_id,
+                &reserve_pubkey,
+                reserve,
+                config,
+                &sender,
+                &thread_shared,
+            )
+        });
+
+        Self {
+            events,
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Receiver of reserve events
+    pub fn events(&self) -> &Receiver<ReserveEvent> {
+        &self.events
+    }
+}
+
+impl Drop for ReserveWatcher {
+    /// Stops the background thread without waiting for it. Joining it and shutting down the
+    /// subscription happen on a cleanup thread, since with solana-client 1.14 an active
+    /// subscription is shut down only once its websocket yields the next message or closes.
+    fn drop(&mut self) {
+        self.shared.exit.store(true, Ordering::Relaxed);
+        let thread = self.thread.take();
+        let shared = self.shared.clone();
+        thread::spawn(move || {
+            if let Some(thread) = thread {
+                let _ = thread.join();
+            }
+            // The thread is stopped, so the subscription is not replaced anymore
+            shared.shutdown_subscription();
+        });
+    }
+}
+
+fn watch(
+    url: &str,
+    program_id: &Pubkey,
+    reserve_pubkey: &Pubkey,
+    mut reserve: Option<Reserve>,
+    config: ReserveWatcherConfig,
+    sender: &Sender<ReserveEvent>,
+    shared: &Shared,
+) {
+    let account_config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(config.commitment),
+        ..RpcAccountInfoConfig::default()
+    };
+
+    while !shared.exit() {
+        if let Ok((new_subscription, updates)) =
+            PubsubClient::account_subscribe(url, reserve_pubkey, Some(account_config.clone()))
+        {
+            *shared.subscription.lock().unwrap() = Some(new_subscription);
+            if sender.send(ReserveEvent::Subscribed).is_err() {
+                return;
+            }
+            while !shared.exit() {
+                let events = match updates.recv_timeout(POLL_INTERVAL) {
+                    Ok(update) => reserve_update(program_id, &mut reserve, &update.value),
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => vec![ReserveEvent::Disconnected],
+                };
+                let disconnected = events.contains(&ReserveEvent::Disconnected);
+                for event in events {
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+                if disconnected {
+                    // Its receive thread has already exited, so this does not block
+                    shared.shutdown_subscription();
+                    break;
+                }
+            }
+        }
+
+        // Sleep in steps to notice when stopped
+        let mut delay = config.reconnect_delay;
+        while delay > Duration::ZERO && !shared.exit() {
+            let step = delay.min(POLL_INTERVAL);
+            thread::sleep(step);
+            delay -= step;
+        }
+    }
+}
+
+/// Decodes `account` and returns its changes compared with the last known `reserve`
+fn reserve_update(
+    program_id: &Pubkey,
+    reserve: &mut Option<Reserve>,
+    account: &UiAccount,
+) -> Vec<ReserveEvent> {
+    let new = match account
+        .decode::<Account>()
+        .ok_or(FlashSdkError::DeserializationError)
+        .and_then(|account| unpack_reserve(program_id, &account.owner, &account.data))
+    {
+        Ok(new) => new,
+        Err(err) => return vec![ReserveEvent::InvalidUpdate(err)],
+    };
+
+    let mut events = vec![];
+    if let Some(old) = reserve {
+        if old.liquidity.available_amount != new.liquidity.available_amount {
+            events.push(ReserveEvent::LiquidityChanged {
+                old: old.liquidity.available_amount,
+                new: new.liquidity.available_amount,
+            });
+        }
+        if old.config.fees != new.config.fees {
+            events.push(ReserveEvent::FeeChanged {
+                old: old.config.fees,
+                new: new.config.fees,
+            });
+        }
+    }
+    *reserve = Some(new);
+
+    events
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    use serde_json::json;
+    use tungstenite::{accept, Message, WebSocket};
+
+    use super::*;
+    use crate::testing::ReserveBuilder;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Stand-in for the RPC node websocket: accepts a subscription and answers it
+    fn subscribe(listener: &TcpListener) -> WebSocket<std::net::TcpStream> {
+        let (stream, _) = listener.accept().unwrap();
+        let mut socket = accept(stream).unwrap();
+        let request: serde_json::Value =
+            serde_json::from_str(&socket.read_message().unwrap().into_text().unwrap()).unwrap();
+        assert_eq!(request["method"], "accountSubscribe");
+        assert_eq!(request["params"][1]["encoding"], "base64");
+        socket
+            .write_message(Message::Text(
+                json!({"jsonrpc": "2.0", "result": 7, "id": request["id"]}).to_string(),
+            ))
+            .unwrap();
+        socket
+    }
+
+    fn notify(socket: &mut WebSocket<std::net::TcpStream>, slot: u64, account: &Account) {
+        let account = UiAccount::encode(
+            &Pubkey::new_unique(),
+            account,
+            UiAccountEncoding::Base64,
+            None,
+            None,
+        );
+        socket
+            .write_message(Message::Text(
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "accountNotification",
+                    "params": {
+                        "result": {"context": {"slot": slot}, "value": account},
+                        "subscription": 7,
+                    },
+                })
+                .to_string(),
+            ))
+            .unwrap();
+    }
+
+    fn next_event(watcher: &ReserveWatcher) -> ReserveEvent {
+        watcher.events().recv_timeout(TIMEOUT).unwrap()
+    }
+
+    #[test]
+    fn watch_reserve_changes_and_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let builder = ReserveBuilder::new().available_liquidity(100);
+        let program_id = builder.account().owner;
+        let (done, server_done) = channel();
+
+        let server_builder = builder.clone();
+        let server = thread::spawn(move || {
+            let builder = server_builder;
+            let mut socket = subscribe(&listener);
+            notify(&mut socket, 1, &builder.account());
+            let builder = builder.available_liquidity(200);
+            notify(&mut socket, 2, &builder.account());
+            let builder = builder.fee_bps(50);
+            notify(&mut socket, 3, &builder.account());
+            drop(socket);
+
+            let mut socket = subscribe(&listener);
+            let builder = builder.available_liquidity(300);
+            notify(&mut socket, 4, &builder.account());
+            let mut invalid = builder.account();
+            invalid.owner = Pubkey::new_unique();
+            notify(&mut socket, 5, &invalid);
+            server_done.recv().unwrap();
+            socket.close(None).unwrap();
+        });
+
+        let watcher = ReserveWatcher::start(
+            &url,
+            program_id,
+            Pubkey::new_unique(),
+            None,
+            ReserveWatcherConfig {
+                reconnect_delay: Duration::from_millis(10),
+                ..ReserveWatcherConfig::default()
+            },
+        );
+
+        let fees = builder.build().config.fees;
+        assert_eq!(next_event(&watcher), ReserveEvent::Subscribed);
+        // The first update is the baseline
+        assert_eq!(
+            next_event(&watcher),
+            ReserveEvent::LiquidityChanged { old: 100, new: 200 }
+        );
+        assert_eq!(
+            next_event(&watcher),
+            ReserveEvent::FeeChanged {
+                old: fees,
+                new: builder.clone().fee_bps(50).build().config.fees,
+            }
+        );
+        assert_eq!(next_event(&watcher), ReserveEvent::Disconnected);
+        assert_eq!(next_event(&watcher), ReserveEvent::Subscribed);
+        // Compared with the state before the reconnect
+        assert_eq!(
+            next_event(&watcher),
+            ReserveEvent::LiquidityChanged { old: 200, new: 300 }
+        );
+        assert_eq!(
+            next_event(&watcher),
+            ReserveEvent::InvalidUpdate(FlashSdkError::InvalidReserveOwner)
+        );
+
+        done.send(()).unwrap();
+        server.join().unwrap();
+        drop(watcher);
+    }
+
+    #[test]
+    fn drop_shuts_down_subscription() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let builder = ReserveBuilder::new();
+        let program_id = builder.account().owner;
+
+        let server = thread::spawn(move || {
+            let mut socket = subscribe(&listener);
+            // The subscription receives this while shutting down
+            thread::sleep(Duration::from_millis(200));
+            notify(&mut socket, 1, &builder.account());
+            let request: serde_json::Value =
+                serde_json::from_str(&socket.read_message().unwrap().into_text().unwrap()).unwrap();
+            assert_eq!(request["method"], "accountUnsubscribe");
+            assert_eq!(request["params"], json!([7]));
+        });
+
+        let watcher = ReserveWatcher::start(
+            &url,
+            program_id,
+            Pubkey::new_unique(),
+            None,
+            ReserveWatcherConfig::default(),
+        );
+        assert_eq!(next_event(&watcher), ReserveEvent::Subscribed);
+        drop(watcher);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn drop_without_waiting_for_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (dropped, server_dropped) = channel();
+
+        let server = thread::spawn(move || {
+            let mut socket = subscribe(&listener);
+            // Silent until the watcher is dropped, then closes the connection. The timeout lets
+            // a blocking drop return, to fail the test rather than hang.
+            let _ = server_dropped.recv_timeout(Duration::from_secs(5));
+            socket.close(None).unwrap();
+            // The cleanup thread unsubscribes once the connection is closed
+            while socket.read_message().is_ok() {}
+        });
+
+        let watcher = ReserveWatcher::start(
+            &url,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+            ReserveWatcherConfig::default(),
+        );
+        assert_eq!(next_event(&watcher), ReserveEvent::Subscribed);
+        let start = Instant::now();
+        drop(watcher);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let _ = dropped.send(());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn compare_with_known_reserve() {
+        let builder = ReserveBuilder::new().available_liquidity(100);
+        let program_id = builder.account().owner;
+        let mut reserve = Some(builder.build());
+        let account = |builder: &ReserveBuilder| {
+            UiAccount::encode(
+                &Pubkey::new_unique(),
+                &builder.account(),
+                UiAccountEncoding::Base64,
+                None,
+                None,
+            )
+        };
+
+        assert!(reserve_update(&program_id, &mut reserve, &account(&builder)).is_empty());
+        assert_eq!(
+            reserve_update(
+                &program_id,
+                &mut reserve,
+                &account(&builder.clone().available_liquidity(50))
+            ),
+            vec![ReserveEvent::LiquidityChanged { old: 100, new: 50 }]
+        );
+        assert_eq!(reserve.unwrap().liquidity.available_amount, 50);
+        assert_eq!(
+            reserve_update(&Pubkey::new_unique(), &mut reserve, &account(&builder)),
+            vec![ReserveEvent::InvalidUpdate(
+                FlashSdkError::InvalidReserveOwner
+            )]
+        );
+    }
+}